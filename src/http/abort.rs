@@ -0,0 +1,48 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Waker},
+};
+
+/// Aborts requests from anywhere, e.g. another task than the one awaiting a spawned request.
+///
+/// Attach clones of a handle to requests with [`RequestSend::with_abort_handle`](crate::RequestSend::with_abort_handle).
+/// Once [`abort`](AbortHandle::abort) is called, they fail with [`HttpError::Aborted`](crate::HttpError::Aborted) the
+/// next time they are polled, including reads of their response bodies, and their connections are closed.
+#[derive(Clone, Debug, Default)]
+pub struct AbortHandle {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    aborted: AtomicBool,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl AbortHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn abort(&self) {
+        self.inner.aborted.store(true, Ordering::SeqCst);
+        for waker in self.inner.wakers.lock().unwrap().drain(..) {
+            waker.wake();
+        }
+    }
+    pub fn is_aborted(&self) -> bool {
+        self.inner.aborted.load(Ordering::SeqCst)
+    }
+    // Registers the task to be woken by `abort`, and checks afterwards so that an abort in between isn't missed.
+    pub(crate) fn poll_aborted(&self, cx: &mut Context<'_>) -> bool {
+        {
+            let mut wakers = self.inner.wakers.lock().unwrap();
+            if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                wakers.push(cx.waker().clone());
+            }
+        }
+        self.is_aborted()
+    }
+}
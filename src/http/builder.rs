@@ -0,0 +1,98 @@
+use std::{sync::Arc, time::Duration};
+
+use http::{header, HeaderName, HeaderValue, Method, Response, Uri};
+
+use super::client::Client;
+use super::error::HttpError;
+use super::timeouts::{Deadline, Timeouts};
+use super::ResponseRead;
+
+/// Builds and sends a request with the configuration of a [`Client`]. Created by [`Client::request`].
+///
+/// Errors of the builder methods, e.g. invalid header values, are returned by [`send`](RequestBuilder::send).
+pub struct RequestBuilder<'c> {
+    client: &'c Client,
+    builder: http::request::Builder,
+    body: Vec<u8>,
+    timeouts: Option<Timeouts>,
+    timeout: Option<Duration>,
+    error: Option<HttpError>,
+}
+
+impl<'c> RequestBuilder<'c> {
+    pub(crate) fn new<U: TryInto<Uri>>(client: &'c Client, method: Method, uri: U) -> Self
+    where
+        U::Error: Into<http::Error>,
+    {
+        Self {
+            client,
+            builder: http::Request::builder().method(method).uri(uri),
+            body: Vec::new(),
+            timeouts: None,
+            timeout: None,
+            error: None,
+        }
+    }
+    /// Appends a header, keeping previous values of the same name.
+    pub fn header<K: TryInto<HeaderName>, V: TryInto<HeaderValue>>(mut self, name: K, value: V) -> Self
+    where
+        K::Error: Into<http::Error>,
+        V::Error: Into<http::Error>,
+    {
+        self.builder = self.builder.header(name, value);
+        self
+    }
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+    /// Limits the time for the whole request including reading the body, measured from [`send`](RequestBuilder::send).
+    /// Fails with [`HttpError::DeadlineExceeded`] once it elapses.
+    ///
+    /// Replaces the timeout of the client for this request.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+    /// Replaces the per-phase timeouts of the client for this request.
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = Some(timeouts);
+        self
+    }
+    /// Sets the `Authorization` header for HTTP basic authentication.
+    pub fn basic_auth(self, username: &str, password: Option<&str>) -> Self {
+        let credentials = base64::encode(format!("{username}:{}", password.unwrap_or_default()));
+        self.sensitive_header(header::AUTHORIZATION, format!("Basic {credentials}"))
+    }
+    /// Sets the `Authorization` header to a bearer token.
+    pub fn bearer_auth(self, token: &str) -> Self {
+        self.sensitive_header(header::AUTHORIZATION, format!("Bearer {token}"))
+    }
+    /// Sends the request without following redirects.
+    pub async fn send(self) -> Result<Response<ResponseRead>, HttpError> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        let request = self.builder.body(self.body).map_err(|err| HttpError::InvalidRequest(Arc::new(err)))?;
+        let mut send = self.client.send(&request);
+        let options = send.inner.options_mut();
+        if let Some(timeouts) = self.timeouts {
+            options.timeouts = timeouts;
+        }
+        if let Some(timeout) = self.timeout {
+            options.deadline = Some(Deadline::after(timeout));
+        }
+        send.await
+    }
+    // Marked sensitive so that credentials don't show up in debug output.
+    fn sensitive_header(mut self, name: HeaderName, value: String) -> Self {
+        match HeaderValue::try_from(value) {
+            Ok(mut value) => {
+                value.set_sensitive(true);
+                self.builder = self.builder.header(name, value);
+            }
+            Err(err) => self.error = Some(HttpError::InvalidRequest(Arc::new(err.into()))),
+        }
+        self
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use futures::AsyncReadExt;
+use http::{header, HeaderMap, Method, Request, Response, StatusCode, Version};
+
+use super::client::Client;
+use super::common::parse_http_date;
+use super::conditional::with_etag;
+use super::error::HttpError;
+use super::redirect::owned_request;
+use super::ResponseRead;
+
+/// Storage for responses cached by [`Client::send_cached`], keyed by request URI.
+pub trait CacheStorage: Send + Sync {
+    fn get(&self, key: &str) -> Option<CachedResponse>;
+    fn put(&self, key: &str, response: CachedResponse);
+    fn remove(&self, key: &str);
+}
+
+/// A response with its complete body, as kept by a [`CacheStorage`].
+#[derive(Clone, Debug)]
+pub struct CachedResponse {
+    pub status: StatusCode,
+    pub version: Version,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+    /// When the response was received or last revalidated.
+    pub received: SystemTime,
+}
+
+impl CachedResponse {
+    fn from_response(response: &Response<Vec<u8>>) -> Self {
+        Self {
+            status: response.status(),
+            version: response.version(),
+            headers: response.headers().clone(),
+            body: response.body().clone(),
+            received: SystemTime::now(),
+        }
+    }
+    fn to_response(&self) -> Response<Vec<u8>> {
+        let mut response = Response::new(self.body.clone());
+        *response.status_mut() = self.status;
+        *response.version_mut() = self.version;
+        *response.headers_mut() = self.headers.clone();
+        response.headers_mut().insert(header::AGE, self.age().as_secs().into());
+        response
+    }
+    // The `Age` the response already had when it was received, plus the time it has been stored since.
+    fn age(&self) -> Duration {
+        let initial = self.headers.get(header::AGE).and_then(|v| v.to_str().ok()?.parse().ok()).unwrap_or(0);
+        Duration::from_secs(initial) + SystemTime::now().duration_since(self.received).unwrap_or_default()
+    }
+    fn is_fresh(&self) -> bool {
+        self.age() < freshness_lifetime(&self.headers, self.received)
+    }
+}
+
+/// How a response returned by [`Client::send_cached`] was obtained. Stored in the extensions of every such response and
+/// available through [`ResponseExt::cache_status`](super::ResponseExt::cache_status).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CacheStatus {
+    /// Served from the cache without contacting the server.
+    Hit,
+    /// Served from the cache after the server confirmed it with `304 Not Modified`.
+    Revalidated,
+    /// Fetched from the server, because nothing usable was cached or the request bypasses the cache.
+    Miss,
+}
+
+/// A [`CacheStorage`] that keeps responses in memory.
+///
+/// Unless created with [`with_capacity`](MemoryCache::with_capacity), the number of entries is unbounded.
+#[derive(Debug, Default)]
+pub struct MemoryCache {
+    entries: Mutex<HashMap<String, CachedResponse>>,
+    capacity: Option<usize>,
+}
+
+impl MemoryCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Keeps at most `capacity` responses, evicting the least recently received one when full.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::default(),
+            capacity: Some(capacity),
+        }
+    }
+}
+
+impl CacheStorage for MemoryCache {
+    fn get(&self, key: &str) -> Option<CachedResponse> {
+        self.entries.lock().unwrap().get(key).cloned()
+    }
+    fn put(&self, key: &str, response: CachedResponse) {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(capacity) = self.capacity {
+            while entries.len() >= capacity && !entries.contains_key(key) {
+                let Some(oldest) = entries.iter().min_by_key(|(_, entry)| entry.received).map(|(key, _)| key.clone()) else {
+                    return;
+                };
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(key.to_string(), response);
+    }
+    fn remove(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+}
+
+pub(crate) async fn send_cached(
+    client: &Client,
+    cache: Option<&dyn CacheStorage>,
+    request: &Request<impl AsRef<[u8]>>,
+) -> Result<Response<Vec<u8>>, HttpError> {
+    let Some(cache) = cache else {
+        return read_body(client.send(request).await?).await;
+    };
+    let key = request.uri().to_string();
+    if request.method() != Method::GET {
+        // Unsafe methods may change the resource, so a cached representation can't be trusted anymore.
+        if !request.method().is_safe() {
+            cache.remove(&key);
+        }
+        return read_body(client.send(request).await?).await;
+    }
+    let directives = CacheControl::from_headers(request.headers());
+    if directives.no_store {
+        return read_body(client.send(request).await?).await;
+    }
+    let cached = cache.get(&key);
+    if let Some(entry) = &cached {
+        if !directives.no_cache && directives.max_age != Some(0) && entry.is_fresh() {
+            return Ok(with_status(entry.to_response(), CacheStatus::Hit));
+        }
+    }
+    let mut request = owned_request(request);
+    if let Some(entry) = &cached {
+        if !request.headers().contains_key(header::IF_NONE_MATCH) {
+            if let Some(etag) = entry.headers.get(header::ETAG) {
+                request = with_etag(request, etag.clone());
+            }
+        }
+        if let Some(last_modified) = entry.headers.get(header::LAST_MODIFIED) {
+            request.headers_mut().entry(header::IF_MODIFIED_SINCE).or_insert(last_modified.clone());
+        }
+    }
+    let response = client.send(&request).await?;
+    if let (StatusCode::NOT_MODIFIED, Some(mut entry)) = (response.status(), cached) {
+        let (parts, mut body) = response.into_parts();
+        body.trailers().await?;
+        // The headers of the 304 replace the stored ones, except for those describing the body, which it doesn't have.
+        for name in parts.headers.keys() {
+            if [header::CONTENT_LENGTH, header::CONTENT_ENCODING, header::TRANSFER_ENCODING].contains(name) {
+                continue;
+            }
+            entry.headers.remove(name);
+            for value in parts.headers.get_all(name) {
+                entry.headers.append(name, value.clone());
+            }
+        }
+        entry.received = SystemTime::now();
+        cache.put(&key, entry.clone());
+        return Ok(with_status(entry.to_response(), CacheStatus::Revalidated));
+    }
+    let response = read_body(response).await?;
+    match is_storable(&response) {
+        true => cache.put(&key, CachedResponse::from_response(&response)),
+        false => cache.remove(&key),
+    }
+    Ok(response)
+}
+
+// Responses fetched from the server are marked as misses here, also when they are stored afterwards.
+async fn read_body(response: Response<ResponseRead>) -> Result<Response<Vec<u8>>, HttpError> {
+    let (parts, mut body) = response.into_parts();
+    let mut buf = Vec::new();
+    body.read_to_end(&mut buf).await?;
+    Ok(with_status(Response::from_parts(parts, buf), CacheStatus::Miss))
+}
+
+fn with_status(mut response: Response<Vec<u8>>, status: CacheStatus) -> Response<Vec<u8>> {
+    response.extensions_mut().insert(status);
+    response
+}
+
+// Only complete `200` responses are stored. Responses with `Vary` are skipped, since entries are keyed by URI only.
+fn is_storable(response: &Response<Vec<u8>>) -> bool {
+    let headers = response.headers();
+    let validator = headers.contains_key(header::ETAG) || headers.contains_key(header::LAST_MODIFIED);
+    response.status() == StatusCode::OK
+        && !CacheControl::from_headers(headers).no_store
+        && !headers.contains_key(header::VARY)
+        && (validator || !freshness_lifetime(headers, SystemTime::now()).is_zero())
+}
+
+// Without `max-age` or `Expires` a response is considered stale right away, no heuristic freshness is applied.
+fn freshness_lifetime(headers: &HeaderMap, received: SystemTime) -> Duration {
+    let directives = CacheControl::from_headers(headers);
+    if directives.no_cache {
+        return Duration::ZERO;
+    }
+    if let Some(max_age) = directives.max_age {
+        return Duration::from_secs(max_age);
+    }
+    let Some(expires) = headers.get(header::EXPIRES) else {
+        return Duration::ZERO;
+    };
+    let date = headers
+        .get(header::DATE)
+        .and_then(|v| parse_http_date(v.to_str().ok()?))
+        .unwrap_or(received);
+    // Invalid values like `0` mean that the response has already expired.
+    match expires.to_str().ok().and_then(parse_http_date) {
+        Some(expires) => expires.duration_since(date).unwrap_or_default(),
+        None => Duration::ZERO,
+    }
+}
+
+#[derive(Default)]
+struct CacheControl {
+    no_store: bool,
+    no_cache: bool,
+    max_age: Option<u64>,
+}
+
+impl CacheControl {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let mut directives = Self::default();
+        let values = headers.get_all(header::CACHE_CONTROL).into_iter().filter_map(|v| v.to_str().ok());
+        for directive in values.flat_map(|v| v.split(',')) {
+            let (name, value) = directive.split_once('=').unwrap_or((directive, ""));
+            let name = name.trim();
+            if name.eq_ignore_ascii_case("no-store") {
+                directives.no_store = true;
+            } else if name.eq_ignore_ascii_case("no-cache") {
+                directives.no_cache = true;
+            } else if name.eq_ignore_ascii_case("max-age") {
+                directives.max_age = value.trim().trim_matches('"').parse().ok();
+            }
+        }
+        directives
+    }
+}
@@ -0,0 +1,100 @@
+use std::{sync::Arc, time::Duration};
+
+use http::{header, HeaderMap, HeaderName, HeaderValue, Method, Request, Response, Uri};
+
+use super::error::HttpError;
+use super::{RequestSend, ResponseRead};
+
+/// What a server advertises in the response to an `OPTIONS` request.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The methods from the `Allow` header.
+    pub allow: Vec<Method>,
+    pub cors: Cors,
+}
+
+/// The `Access-Control-Allow-*` headers of a CORS preflight response.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cors {
+    pub allow_origin: Option<HeaderValue>,
+    pub allow_methods: Vec<Method>,
+    pub allow_headers: Vec<HeaderName>,
+    pub allow_credentials: bool,
+    pub max_age: Option<Duration>,
+}
+
+impl Capabilities {
+    /// Parses the headers of a response, skipping list entries that aren't valid methods or header names.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        Self {
+            allow: list(headers, header::ALLOW)
+                .filter_map(|m| Method::from_bytes(m.as_bytes()).ok())
+                .collect(),
+            cors: Cors {
+                allow_origin: headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).cloned(),
+                allow_methods: list(headers, header::ACCESS_CONTROL_ALLOW_METHODS)
+                    .filter_map(|m| Method::from_bytes(m.as_bytes()).ok())
+                    .collect(),
+                allow_headers: list(headers, header::ACCESS_CONTROL_ALLOW_HEADERS)
+                    .filter_map(|h| HeaderName::try_from(h).ok())
+                    .collect(),
+                allow_credentials: headers
+                    .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+                    .is_some_and(|v| v.as_bytes().trim_ascii() == b"true"),
+                max_age: headers
+                    .get(header::ACCESS_CONTROL_MAX_AGE)
+                    .and_then(|v| v.to_str().ok()?.trim().parse().ok())
+                    .map(Duration::from_secs),
+            },
+        }
+    }
+    /// Whether `method` is listed in `Allow`.
+    pub fn allows(&self, method: &Method) -> bool {
+        self.allow.contains(method)
+    }
+}
+
+/// Sends an `OPTIONS` request for `uri` and parses the capabilities from the response.
+///
+/// The response is returned as well, e.g. to check its status, since servers that don't support `OPTIONS` typically answer
+/// with `405 Method Not Allowed` and no `Allow` header.
+pub async fn options<U: TryInto<Uri>>(uri: U) -> Result<(Capabilities, Response<ResponseRead>), HttpError>
+where
+    U::Error: Into<http::Error>,
+{
+    let request = options_request(uri)?;
+    let response = RequestSend::new(&request).await?;
+    Ok((Capabilities::from_headers(response.headers()), response))
+}
+
+/// Like [`options`], but asks about the server as a whole with `OPTIONS *`. Only the authority of `uri` is used.
+pub async fn server_options<U: TryInto<Uri>>(uri: U) -> Result<(Capabilities, Response<ResponseRead>), HttpError>
+where
+    U::Error: Into<http::Error>,
+{
+    let request = options_request(uri)?;
+    let response = RequestSend::new(&request).with_request_target(Uri::from_static("*")).await?;
+    Ok((Capabilities::from_headers(response.headers()), response))
+}
+
+fn options_request<U: TryInto<Uri>>(uri: U) -> Result<Request<Vec<u8>>, HttpError>
+where
+    U::Error: Into<http::Error>,
+{
+    Request::builder()
+        .method(Method::OPTIONS)
+        .uri(uri)
+        .body(Vec::new())
+        .map_err(|err| HttpError::InvalidRequest(Arc::new(err)))
+}
+
+// Comma separated list values, possibly spread over several header lines.
+fn list(headers: &HeaderMap, name: HeaderName) -> impl Iterator<Item = &str> {
+    headers
+        .get_all(name)
+        .into_iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+}
@@ -0,0 +1,117 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{ready, AsyncRead};
+use http::{header::HeaderName, HeaderMap, HeaderValue};
+
+use super::error::HttpError;
+
+const MAX_LINE: usize = 8192;
+
+// Decodes a chunked body including its trailer section.
+//
+// Size lines and trailers are read byte by byte, so nothing past the end of the body is consumed from the transport.
+pub(crate) struct ChunkedDecoder {
+    state: ChunkedState,
+    line: Vec<u8>,
+    trailers: HeaderMap,
+}
+
+enum ChunkedState {
+    Size,
+    Data { remaining: u64 },
+    DataEnd,
+    Trailers,
+    Done,
+}
+
+impl ChunkedDecoder {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: ChunkedState::Size,
+            line: Vec::new(),
+            trailers: HeaderMap::new(),
+        }
+    }
+    pub(crate) fn trailers(&self) -> &HeaderMap {
+        &self.trailers
+    }
+    pub(crate) fn poll_read<T: AsyncRead + Unpin>(&mut self, transport: &mut T, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        loop {
+            match &mut self.state {
+                ChunkedState::Size => {
+                    let line = ready!(self.poll_line(transport, cx))?;
+                    let line = std::str::from_utf8(&line).map_err(|_| invalid("invalid chunk size"))?;
+                    // Chunk extensions (`1a;name=value`) are ignored. Signs aren't hex digits, even though `from_str_radix` accepts a
+                    // leading `+`, and sizes that overflow are rejected by it.
+                    let size = line.split(';').next().unwrap().trim_matches([' ', '\t']);
+                    if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+                        return Poll::Ready(Err(invalid("invalid chunk size")));
+                    }
+                    let size = u64::from_str_radix(size, 16).map_err(|_| invalid("chunk size too large"))?;
+                    self.state = match size {
+                        0 => ChunkedState::Trailers,
+                        remaining => ChunkedState::Data { remaining },
+                    };
+                }
+                ChunkedState::Data { remaining } => {
+                    if buf.is_empty() {
+                        return Poll::Ready(Ok(0));
+                    }
+                    let max = buf.len().min(usize::try_from(*remaining).unwrap_or(usize::MAX));
+                    let n = ready!(Pin::new(&mut *transport).poll_read(cx, &mut buf[..max]))?;
+                    if n == 0 {
+                        return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                    }
+                    *remaining -= n as u64;
+                    if *remaining == 0 {
+                        self.state = ChunkedState::DataEnd;
+                    }
+                    return Poll::Ready(Ok(n));
+                }
+                ChunkedState::DataEnd => {
+                    if !ready!(self.poll_line(transport, cx))?.is_empty() {
+                        return Poll::Ready(Err(invalid("missing CRLF after chunk data")));
+                    }
+                    self.state = ChunkedState::Size;
+                }
+                ChunkedState::Trailers => {
+                    let line = ready!(self.poll_line(transport, cx))?;
+                    if line.is_empty() {
+                        self.state = ChunkedState::Done;
+                        continue;
+                    }
+                    let colon = line.iter().position(|b| *b == b':').ok_or_else(|| invalid("invalid trailer"))?;
+                    let name = HeaderName::from_bytes(&line[..colon]).map_err(|_| invalid("invalid trailer name"))?;
+                    let value = HeaderValue::from_bytes(line[colon + 1..].trim_ascii()).map_err(|_| invalid("invalid trailer value"))?;
+                    self.trailers.append(name, value);
+                }
+                ChunkedState::Done => return Poll::Ready(Ok(0)),
+            }
+        }
+    }
+    // Reads one line without its line ending, keeping partial lines in `self.line` across polls.
+    fn poll_line<T: AsyncRead + Unpin>(&mut self, transport: &mut T, cx: &mut Context<'_>) -> Poll<io::Result<Vec<u8>>> {
+        loop {
+            let mut byte = [0u8];
+            match ready!(Pin::new(&mut *transport).poll_read(cx, &mut byte))? {
+                0 => return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into())),
+                _ if byte[0] == b'\n' => {
+                    if self.line.last() == Some(&b'\r') {
+                        self.line.pop();
+                    }
+                    return Poll::Ready(Ok(std::mem::take(&mut self.line)));
+                }
+                _ if self.line.len() >= MAX_LINE => return Poll::Ready(Err(invalid("chunk line too long"))),
+                _ => self.line.push(byte[0]),
+            }
+        }
+    }
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    HttpError::InvalidChunk(msg).into()
+}
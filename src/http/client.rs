@@ -0,0 +1,219 @@
+use std::{pin::pin, sync::Arc, time::Duration};
+
+use async_io::Timer;
+use futures::future::{select, Either};
+use http::{HeaderMap, Method, Request, Response, Uri};
+
+use crate::{ClientConfig, Transport, DEFAULT_CLIENT_CONFIG};
+
+use super::builder::RequestBuilder;
+#[cfg(feature = "cache")]
+use super::cache::{send_cached, CacheStorage};
+use super::connection::Connection;
+use super::credentials::{CredentialProvider, Credentials};
+use super::error::HttpError;
+use super::observer::Observer;
+use super::range::download_ranges;
+use super::redirect::{owned_request, redirect_request, RedirectPolicy};
+use super::request_native::{connect_target, RequestOptions};
+use super::schemes::SchemeConfig;
+use super::timeouts::{Deadline, TimeoutPhase, Timeouts};
+use super::{RequestSend, ResponseRead};
+
+/// Shared configuration for sending requests.
+#[derive(Clone)]
+pub struct Client {
+    client_config: Arc<ClientConfig>,
+    options: RequestOptions,
+    max_redirects: usize,
+    redirect_policy: Option<Arc<RedirectPolicy>>,
+    timeout: Option<Duration>,
+    #[cfg(feature = "cache")]
+    cache: Option<Arc<dyn CacheStorage>>,
+}
+
+impl Client {
+    pub fn new() -> Self {
+        Self {
+            client_config: DEFAULT_CLIENT_CONFIG.clone(),
+            options: RequestOptions::default(),
+            max_redirects: 10,
+            redirect_policy: None,
+            timeout: None,
+            #[cfg(feature = "cache")]
+            cache: None,
+        }
+    }
+    #[cfg(feature = "tls")]
+    pub fn with_client_config(mut self, client_config: Arc<ClientConfig>) -> Self {
+        self.client_config = client_config;
+        self
+    }
+    pub fn with_observer(mut self, observer: Arc<dyn Observer>) -> Self {
+        self.options.observer = Some(observer);
+        self
+    }
+    pub fn with_schemes(mut self, schemes: SchemeConfig) -> Self {
+        self.options.schemes = schemes;
+        self
+    }
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.options.timeouts = timeouts;
+        self
+    }
+    /// Limits the time for each request including reading the body, measured from when it's sent. Requests fail with
+    /// [`HttpError::DeadlineExceeded`] once it elapses.
+    ///
+    /// Applies in addition to the per-phase [`Timeouts`]. A deadline set on a single request replaces it.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+    /// Enables TCP keepalive probes on new connections after they have been idle for `time`. Off by default.
+    pub fn with_tcp_keepalive(mut self, time: Duration) -> Self {
+        self.options.tcp_keepalive = Some(time);
+        self
+    }
+    /// Makes requests fail with [`HttpError::Status`] on 4xx and 5xx responses, see [`RequestSend::error_for_status`].
+    pub fn error_for_status(mut self) -> Self {
+        self.options.error_for_status = true;
+        self
+    }
+    /// Skips the body of responses for which `should_read_body` returns `false`, see [`RequestSend::should_read_body`].
+    pub fn should_read_body(mut self, should_read_body: impl Fn(&http::Response<()>) -> bool + Send + Sync + 'static) -> Self {
+        self.options.should_read_body = Some(Arc::new(should_read_body));
+        self
+    }
+    /// Sends a bearer token from `provider` in the `Authorization` header of every request that doesn't have one.
+    ///
+    /// The token is fetched before the first request and cached until shortly before it expires. Clones of the client
+    /// share the cached token. Redirects that leave the origin of the original request are sent without it.
+    pub fn with_credentials(mut self, provider: Arc<dyn CredentialProvider>) -> Self {
+        self.options.credentials = Some(Arc::new(Credentials::new(provider)));
+        self
+    }
+    /// Makes requests fail with [`HttpError::InvalidHeader`] on response headers with invalid bytes, see
+    /// [`RequestSend::strict_headers`].
+    pub fn strict_headers(mut self) -> Self {
+        self.options.strict_headers = true;
+        self
+    }
+    /// The number of redirects [`send_following_redirects`](Client::send_following_redirects) follows before it returns
+    /// the redirect response itself. Defaults to 10.
+    pub fn with_max_redirects(mut self, max_redirects: usize) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+    /// Called with the target of every redirect before it is followed. Redirects it rejects fail with
+    /// [`HttpError::RedirectBlocked`].
+    pub fn with_redirect_policy(mut self, policy: impl Fn(&http::Uri) -> bool + Send + Sync + 'static) -> Self {
+        self.redirect_policy = Some(Arc::new(policy));
+        self
+    }
+    /// Sends the request and follows redirects to the final response.
+    ///
+    /// 303 responses, and 301 and 302 responses to `POST`, are followed with a `GET` without body. Credentials are removed
+    /// when a redirect leaves the origin of the previous request.
+    pub async fn send_following_redirects(&self, request: &Request<impl AsRef<[u8]>>) -> Result<Response<ResponseRead>, HttpError> {
+        let origin = (request.uri().scheme().cloned(), request.uri().authority().cloned());
+        let mut request = owned_request(request);
+        let mut redirects = 0;
+        loop {
+            let mut send = self.send(&request);
+            if (request.uri().scheme().cloned(), request.uri().authority().cloned()) != origin {
+                send.inner.options_mut().credentials = None;
+            }
+            let response = send.await?;
+            let next = match redirects < self.max_redirects {
+                true => redirect_request(&request, response.status(), response.headers().get(http::header::LOCATION)),
+                false => None,
+            };
+            let Some(next) = next else { return Ok(response) };
+            if let Some(policy) = &self.redirect_policy {
+                if !policy(next.uri()) {
+                    return Err(HttpError::RedirectBlocked(next.uri().clone()));
+                }
+            }
+            trace_event!(status = response.status().as_u16(), location = %next.uri(), "following redirect");
+            request = next;
+            redirects += 1;
+        }
+    }
+    /// Opens a connection without sending a request, e.g. to warm it up before the first request is needed.
+    ///
+    /// `host` is written as in a URI, so IPv6 literals need brackets. The connect timeout and TCP keepalive settings of the
+    /// client apply, and requests sent on the connection use the rest of its configuration, except that redirects aren't
+    /// followed.
+    pub async fn connect(&self, scheme: &str, host: &str, port: u16) -> Result<Connection, HttpError> {
+        let uri = Uri::builder()
+            .scheme(scheme)
+            .authority(format!("{host}:{port}"))
+            .path_and_query("/")
+            .build()
+            .map_err(|err| HttpError::InvalidRequest(Arc::new(err)))?;
+        let (tls, host, port) = connect_target(&uri, &HeaderMap::new(), self.client_config.clone(), &self.options)?;
+        let connect = pin!(Transport::connect(
+            tls,
+            &host,
+            port,
+            self.options.tcp_keepalive,
+            self.options.timeouts.tls_handshake
+        ));
+        let transport = match self.options.timeouts.connect {
+            None => connect.await,
+            Some(timeout) => match select(connect, Timer::after(timeout)).await {
+                Either::Left((result, _)) => result,
+                Either::Right(_) => return Err(HttpError::Timeout(TimeoutPhase::Connect)),
+            },
+        };
+        Ok(Connection::from_transport(transport.map_err(HttpError::from)?)
+            .with_client_config(self.client_config.clone())
+            .with_client_options(self.options.clone(), self.timeout))
+    }
+    /// Downloads the body of `request` with `segments` concurrent range requests and returns it reassembled. At most 32
+    /// segments are used, and never more than the body has bytes.
+    ///
+    /// The length and range support are taken from a `HEAD` request first. If the server doesn't send `Accept-Ranges: bytes`
+    /// and a `Content-Length`, or doesn't answer a range as requested, the body is downloaded with a single `GET` instead.
+    /// The method and body of `request` are ignored. Non-2xx responses fail with [`HttpError::Status`].
+    pub async fn download_parallel<T>(&self, request: &Request<T>, segments: usize) -> Result<Vec<u8>, HttpError> {
+        download_ranges(self, request, segments).await
+    }
+    /// Stores responses to `GET` requests in `cache`, for use with [`send_cached`](Client::send_cached).
+    #[cfg(feature = "cache")]
+    pub fn with_cache(mut self, cache: Arc<dyn CacheStorage>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+    /// Sends the request through the cache and returns the response with its complete body.
+    ///
+    /// Fresh cached responses to `GET` requests are returned without a request, while stale ones with an `ETag` or
+    /// `Last-Modified` header are revalidated with a conditional request. Freshness follows the `max-age`, `no-cache` and
+    /// `no-store` directives of `Cache-Control` and the `Expires` header. Other methods are sent as is, and unsafe ones
+    /// like `POST` drop the cached response for their URI.
+    ///
+    /// Without a cache configured, this just sends the request and reads the body.
+    #[cfg(feature = "cache")]
+    pub async fn send_cached(&self, request: &Request<impl AsRef<[u8]>>) -> Result<Response<Vec<u8>>, HttpError> {
+        send_cached(self, self.cache.as_deref(), request).await
+    }
+    /// Starts building a request that is sent with the configuration of this client.
+    pub fn request<U: TryInto<Uri>>(&self, method: Method, uri: U) -> RequestBuilder<'_>
+    where
+        U::Error: Into<http::Error>,
+    {
+        RequestBuilder::new(self, method, uri)
+    }
+    pub fn send<'a>(&self, request: &'a http::Request<impl AsRef<[u8]>>) -> RequestSend<'a> {
+        let mut send = RequestSend::new_with(request, self.client_config.clone());
+        *send.inner.options_mut() = self.options.clone();
+        send.inner.options_mut().deadline = self.timeout.map(Deadline::after);
+        send
+    }
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,4 +1,8 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Duration, SystemTime};
+
 use http::{
+    header::HeaderName,
     uri::{Authority, Scheme},
     HeaderMap, Uri,
 };
@@ -7,14 +11,88 @@ use super::error::HttpError;
 
 pub(crate) fn extract_origin(uri: &Uri, headers: &HeaderMap) -> Result<(Option<Scheme>, String, Option<u16>), HttpError> {
     if let Some(auth) = uri.authority() {
-        return Ok((uri.scheme().cloned(), auth.host().to_string(), auth.port_u16()));
+        return Ok((uri.scheme().cloned(), auth.host().to_string(), port(auth)?));
+    }
+    match host_header(headers)? {
+        Some((host, port)) => Ok((None, host, port)),
+        None => Err(HttpError::MissingHost(uri.clone())),
+    }
+}
+
+// The host and port of the `Host` header, `None` if it is missing or isn't a plain `host[:port]`.
+pub(crate) fn host_header(headers: &HeaderMap) -> Result<Option<(String, Option<u16>)>, HttpError> {
+    let Some(auth) = headers
+        .get(http::header::HOST)
+        .and_then(|header| Authority::try_from(header.as_bytes()).ok())
+    else {
+        return Ok(None);
+    };
+    let port = port(&auth)?;
+    match auth.as_str().len() == auth.host().len() + auth.port().map(|p| 1usize + p.as_str().len()).unwrap_or(0) {
+        true => Ok(Some((auth.host().to_string(), port))),
+        false => Ok(None),
     }
-    if let Some(header) = headers.get(http::header::HOST) {
-        if let Some(auth) = Authority::try_from(header.as_bytes()).ok() {
-            if auth.as_str().len() == auth.host().len() + 1usize + auth.port().map(|p| p.as_str().len()).unwrap_or(0) {
-                return Ok((None, auth.host().to_string(), auth.port_u16()));
-            }
-        }
+}
+
+// The `Connection` header is a comma separated list that may also be split over several header lines.
+pub(crate) fn connection_tokens(headers: &HeaderMap) -> impl Iterator<Item = &str> {
+    headers
+        .get_all(http::header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+}
+
+pub(crate) fn has_connection_token(headers: &HeaderMap, token: &str) -> bool {
+    connection_tokens(headers).any(|t| t.eq_ignore_ascii_case(token))
+}
+
+/// Removes the `Connection` header and the headers it names, which only apply to a single connection, e.g. before
+/// forwarding the headers of a response in a proxy.
+pub fn remove_connection_headers(headers: &mut HeaderMap) {
+    let named: Vec<HeaderName> = connection_tokens(headers).filter_map(|token| HeaderName::try_from(token).ok()).collect();
+    for name in named {
+        headers.remove(name);
+    }
+    headers.remove(http::header::CONNECTION);
+}
+
+// `Authority::port` silently drops ports that don't fit into a u16, which would make us fall back to the default port.
+fn port(auth: &Authority) -> Result<Option<u16>, HttpError> {
+    let host_port = auth.as_str().rsplit_once('@').map_or(auth.as_str(), |(_, host_port)| host_port);
+    let host_port = host_port.rsplit_once(']').map_or(host_port, |(_, port)| port);
+    match host_port.rsplit_once(':') {
+        None | Some((_, "")) => Ok(None),
+        Some((_, port)) => match port.parse::<u16>() {
+            Ok(0) | Err(_) => Err(HttpError::InvalidPort(port.to_string())),
+            Ok(port) => Ok(Some(port)),
+        },
+    }
+}
+
+// Parses the preferred HTTP date format, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`. The obsolete formats aren't supported.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn parse_http_date(value: &str) -> Option<SystemTime> {
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let (_weekday, rest) = value.trim().split_once(", ")?;
+    let mut parts = rest.split(' ');
+    let day: i64 = parts.next()?.parse().ok()?;
+    let month = parts.next()?;
+    let month = MONTHS.iter().position(|m| *m == month)? as i64 + 1;
+    let year: i64 = parts.next()?.parse().ok()?;
+    let mut time = parts.next()?.split(':').map(|part| part.parse::<u64>().ok());
+    let (hours, minutes, seconds) = (time.next()??, time.next()??, time.next()??);
+    if parts.next()? != "GMT" || parts.next().is_some() || !(1..=31).contains(&day) || hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
     }
-    return Err(HttpError::MissingHost);
+    // Days since 1970-01-01 in the proleptic Gregorian calendar, with years starting in March so leap days come last.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = u64::try_from(era * 146097 + day_of_era - 719468).ok()?;
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(days * 86400 + hours * 3600 + minutes * 60 + seconds))
 }
@@ -0,0 +1,10 @@
+use http::{header, HeaderValue, Request};
+
+/// Adds `etag` to the `If-None-Match` header, so the server can answer with `304 Not Modified` if the cached response
+/// with that entity tag is still current.
+///
+/// Calling it again adds further entity tags instead of replacing the previous ones.
+pub fn with_etag<T>(mut request: Request<T>, etag: HeaderValue) -> Request<T> {
+    request.headers_mut().append(header::IF_NONE_MATCH, etag);
+    request
+}
@@ -0,0 +1,191 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use http::{HeaderMap, Uri};
+
+use crate::{ClientConfig, Transport, DEFAULT_CLIENT_CONFIG};
+
+use super::common::has_connection_token;
+use super::error::HttpError;
+use super::request_native::{connect_target, pipeline, RequestOptions};
+use super::timeouts::Deadline;
+use super::{RequestSend, ResponseRead};
+
+/// Decides whether the connection of a response may be reused, see [`Connection::with_reuse_predicate`].
+pub type ReusePredicate = dyn Fn(&http::Response<()>) -> bool + Send + Sync;
+
+/// A single keep-alive connection for sending requests one after another.
+///
+/// After each response, read its body to the end and hand it back with [`Connection::release`] before sending the next
+/// request.
+///
+/// A `Keep-Alive` header on a response limits how long the connection may stay idle and how many more requests it is used
+/// for. Sending after either limit is reached fails with [`HttpError::ConnectionClosed`].
+///
+/// Before a request is sent on the idle connection, it's checked for having been closed by the server in the meantime. A
+/// closed connection is replaced with a new one to the host of the request, unless it was created with
+/// [`from_transport`](Connection::from_transport), in which case sending fails with [`HttpError::ConnectionClosed`].
+pub struct Connection {
+    transport: Option<Transport>,
+    client_config: Option<Arc<ClientConfig>>,
+    options: RequestOptions,
+    timeout: Option<Duration>,
+    closed: bool,
+    idle_deadline: Option<Instant>,
+    remaining: Option<u32>,
+}
+
+impl Connection {
+    pub async fn connect(uri: &Uri) -> Result<Self, HttpError> {
+        Self::connect_with(uri, DEFAULT_CLIENT_CONFIG.clone()).await
+    }
+    #[cfg(feature = "tls")]
+    pub async fn connect_with_client_config(uri: &Uri, client_config: Arc<ClientConfig>) -> Result<Self, HttpError> {
+        Self::connect_with(uri, client_config).await
+    }
+    async fn connect_with(uri: &Uri, client_config: Arc<ClientConfig>) -> Result<Self, HttpError> {
+        let (tls, host, port) = connect_target(uri, &HeaderMap::new(), client_config.clone(), &RequestOptions::default())?;
+        let transport = Transport::connect(tls, &host, port, None, None).await.map_err(HttpError::from)?;
+        Ok(Self::from_transport(transport).with_client_config(client_config))
+    }
+    pub fn from_transport(transport: Transport) -> Self {
+        Self {
+            transport: Some(transport),
+            client_config: None,
+            options: RequestOptions::default(),
+            timeout: None,
+            closed: false,
+            idle_deadline: None,
+            remaining: None,
+        }
+    }
+    /// Called with the head of every response, the connection is closed after the response if it returns `false`, e.g. to
+    /// not reuse connections that returned a 5xx status.
+    ///
+    /// It can only prevent reuse: connections the server doesn't keep alive are closed regardless.
+    pub fn with_reuse_predicate(mut self, predicate: impl Fn(&http::Response<()>) -> bool + Send + Sync + 'static) -> Self {
+        self.options.reuse_predicate = Some(Arc::new(predicate));
+        self
+    }
+    /// Dropping the returned request before it resolves closes the socket, after which this connection stays busy.
+    pub fn send<'a>(&mut self, request: &'a http::Request<impl AsRef<[u8]>>) -> Result<RequestSend<'a>, HttpError> {
+        if self.idle_deadline.is_some_and(|deadline| deadline <= Instant::now()) || self.remaining == Some(0) {
+            self.close();
+        }
+        if self.closed {
+            return Err(HttpError::ConnectionClosed);
+        }
+        let mut transport = self.transport.take().ok_or(HttpError::ConnectionBusy)?;
+        self.remaining = self.remaining.map(|remaining| remaining - 1);
+        if has_connection_token(request.headers(), "close") {
+            self.closed = true;
+        }
+        if transport.is_stale() {
+            trace_event!("discarding stale connection");
+            drop(transport);
+            let Some(client_config) = &self.client_config else {
+                self.close();
+                return Err(HttpError::ConnectionClosed);
+            };
+            // The fresh connection comes back through `release` like the old one would have.
+            return Ok(self.with_options(RequestSend::new_with(request, client_config.clone())));
+        }
+        Ok(self.with_options(RequestSend::with_transport(request, transport)))
+    }
+    /// Sends all `requests` before reading any response and returns the responses in the same order, with their bodies
+    /// read. On links with a high round trip time this is faster than sending the requests one after another.
+    ///
+    /// Only idempotent methods can be pipelined, others fail with [`HttpError::InvalidMethod`] before anything is sent. If
+    /// the server closes the connection before answering every request, e.g. because of its `Keep-Alive` limit, the
+    /// pipeline fails with [`HttpError::ConnectionClosed`] and the requests can be retried.
+    pub async fn pipeline(&mut self, requests: &[http::Request<impl AsRef<[u8]>>]) -> Result<Vec<http::Response<Vec<u8>>>, HttpError> {
+        let Some(first) = requests.first() else {
+            return Ok(Vec::new());
+        };
+        if let Some(request) = requests.iter().find(|request| !request.method().is_idempotent()) {
+            return Err(HttpError::InvalidMethod(request.method().clone()));
+        }
+        if self.idle_deadline.is_some_and(|deadline| deadline <= Instant::now()) || self.remaining == Some(0) {
+            self.close();
+        }
+        if self.closed || self.remaining.is_some_and(|remaining| (remaining as usize) < requests.len()) {
+            return Err(HttpError::ConnectionClosed);
+        }
+        let mut transport = self.transport.take().ok_or(HttpError::ConnectionBusy)?;
+        self.remaining = self.remaining.map(|remaining| remaining - requests.len() as u32);
+        if transport.is_stale() {
+            trace_event!("discarding stale connection");
+            let Some(client_config) = self.client_config.clone() else {
+                self.close();
+                return Err(HttpError::ConnectionClosed);
+            };
+            transport = match Self::connect_with(first.uri(), client_config).await {
+                Ok(mut connection) => connection.transport.take().unwrap(),
+                Err(err) => {
+                    self.close();
+                    return Err(err);
+                }
+            };
+        }
+        match pipeline(transport, requests, self.request_options()).await {
+            Ok((responses, last)) => {
+                if requests.iter().any(|request| has_connection_token(request.headers(), "close")) {
+                    self.closed = true;
+                }
+                self.release(ResponseRead { inner: last })?;
+                Ok(responses)
+            }
+            Err(err) => {
+                self.close();
+                Err(err)
+            }
+        }
+    }
+    fn with_options<'a>(&self, mut send: RequestSend<'a>) -> RequestSend<'a> {
+        *send.inner.options_mut() = self.request_options();
+        send
+    }
+    fn request_options(&self) -> RequestOptions {
+        RequestOptions {
+            deadline: self.timeout.map(Deadline::after),
+            ..self.options.clone()
+        }
+    }
+    /// Returns the connection of a fully read response body, so that the next request can be sent.
+    ///
+    /// If the server asked to close the connection, the connection is closed instead.
+    pub fn release(&mut self, body: ResponseRead) -> Result<(), HttpError> {
+        let hint = body.keep_alive_hint().unwrap_or_default();
+        self.idle_deadline = hint.timeout.map(|timeout| Instant::now() + timeout);
+        self.remaining = hint.max.or(self.remaining);
+        match body.into_reusable() {
+            Ok(Some(transport)) if !self.closed => self.transport = Some(transport),
+            Ok(_) => self.closed = true,
+            Err(err) => {
+                self.closed = true;
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+    // Allows `send` to reconnect when the idle connection turns out to be closed.
+    pub(crate) fn with_client_config(mut self, client_config: Arc<ClientConfig>) -> Self {
+        self.client_config = Some(client_config);
+        self
+    }
+    // Sends requests with the configuration of a `Client`, `timeout` being its overall request timeout.
+    pub(crate) fn with_client_options(mut self, options: RequestOptions, timeout: Option<Duration>) -> Self {
+        self.options = options;
+        self.timeout = timeout;
+        self
+    }
+    fn close(&mut self) {
+        self.closed = true;
+        self.transport = None;
+    }
+}
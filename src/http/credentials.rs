@@ -0,0 +1,57 @@
+use std::{
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use futures::{lock::Mutex, Future};
+use http::HeaderValue;
+
+use super::error::HttpError;
+
+// Tokens are refreshed this long before they expire, so they don't run out while a request is on its way.
+const REFRESH_MARGIN: Duration = Duration::from_secs(30);
+
+/// A bearer token returned by a [`CredentialProvider`].
+#[derive(Clone, Debug)]
+pub struct BearerToken {
+    pub token: String,
+    /// When the token stops being valid, `None` if it doesn't expire.
+    pub expires_at: Option<Instant>,
+}
+
+/// Fetches bearer tokens that a [`Client`](super::Client) created with
+/// [`with_credentials`](super::Client::with_credentials) sends in the `Authorization` header.
+pub trait CredentialProvider: Send + Sync {
+    /// Fetches a new token. The client keeps it until shortly before it expires and only then asks for another one.
+    fn token(&self) -> Pin<Box<dyn Future<Output = Result<BearerToken, HttpError>> + Send + '_>>;
+}
+
+// The cached `Authorization` value of a provider, shared by all clones of a client.
+pub(crate) struct Credentials {
+    provider: Arc<dyn CredentialProvider>,
+    cached: Mutex<Option<(HeaderValue, Option<Instant>)>>,
+}
+
+impl Credentials {
+    pub(crate) fn new(provider: Arc<dyn CredentialProvider>) -> Self {
+        Self {
+            provider,
+            cached: Mutex::new(None),
+        }
+    }
+    // The lock is held while a token is fetched, so concurrent requests wait for a single refresh.
+    pub(crate) async fn authorization(&self) -> Result<HeaderValue, HttpError> {
+        let mut cached = self.cached.lock().await;
+        if let Some((value, expires_at)) = &*cached {
+            if expires_at.is_none_or(|expires_at| Instant::now() + REFRESH_MARGIN < expires_at) {
+                return Ok(value.clone());
+            }
+        }
+        let token = self.provider.token().await?;
+        let mut value = HeaderValue::try_from(format!("Bearer {}", token.token)).map_err(|err| HttpError::InvalidRequest(Arc::new(err.into())))?;
+        value.set_sensitive(true);
+        *cached = Some((value.clone(), token.expires_at));
+        Ok(value)
+    }
+}
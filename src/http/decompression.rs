@@ -0,0 +1,185 @@
+use std::{
+    io::{self, Write},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use flate2::write::{GzDecoder, ZlibDecoder};
+use futures::{ready, AsyncRead};
+use http::HeaderValue;
+
+#[cfg(not(feature = "brotli"))]
+pub(crate) const ACCEPT_ENCODING: &str = "gzip, deflate";
+#[cfg(feature = "brotli")]
+pub(crate) const ACCEPT_ENCODING: &str = "gzip, deflate, br";
+
+enum Decoder {
+    Gzip(GzDecoder<Vec<u8>>),
+    Deflate(ZlibDecoder<Vec<u8>>),
+    #[cfg(feature = "brotli")]
+    Brotli(Box<brotli_decompressor::DecompressorWriter<Vec<u8>>>),
+}
+
+// Push based decoder: compressed input is written in, decoded output is read back out of the inner buffer.
+pub(crate) struct Decompression {
+    decoder: Decoder,
+    position: usize,
+    received: bool,
+    finished: bool,
+}
+
+impl Decompression {
+    pub(crate) fn from_content_encoding(value: &HeaderValue) -> Option<Self> {
+        let decoder = match value.to_str().ok()?.trim() {
+            v if v.eq_ignore_ascii_case("gzip") || v.eq_ignore_ascii_case("x-gzip") => Decoder::Gzip(GzDecoder::new(Vec::new())),
+            v if v.eq_ignore_ascii_case("deflate") => Decoder::Deflate(ZlibDecoder::new(Vec::new())),
+            #[cfg(feature = "brotli")]
+            v if v.eq_ignore_ascii_case("br") => Decoder::Brotli(Box::new(brotli_decompressor::DecompressorWriter::new(Vec::new(), 4096))),
+            _ => return None,
+        };
+        Some(Self::new(decoder))
+    }
+    fn new(decoder: Decoder) -> Self {
+        Self {
+            decoder,
+            position: 0,
+            received: false,
+            finished: false,
+        }
+    }
+    pub(crate) fn is_finished(&self) -> bool {
+        self.finished && self.position == self.output().len()
+    }
+    // An empty input marks the end of the compressed stream.
+    pub(crate) fn write(&mut self, input: &[u8]) -> io::Result<()> {
+        self.compact();
+        match (input.is_empty(), self.received) {
+            (true, false) => {
+                self.finished = true;
+                Ok(())
+            }
+            (true, true) => {
+                self.finished = true;
+                match &mut self.decoder {
+                    Decoder::Gzip(decoder) => decoder.try_finish(),
+                    Decoder::Deflate(decoder) => decoder.try_finish(),
+                    #[cfg(feature = "brotli")]
+                    Decoder::Brotli(decoder) => decoder.close(),
+                }
+            }
+            (false, _) => {
+                self.received = true;
+                match &mut self.decoder {
+                    Decoder::Gzip(decoder) => decoder.write_all(input).and_then(|()| decoder.flush()),
+                    Decoder::Deflate(decoder) => decoder.write_all(input).and_then(|()| decoder.flush()),
+                    #[cfg(feature = "brotli")]
+                    Decoder::Brotli(decoder) => decoder.write_all(input).and_then(|()| decoder.flush()),
+                }
+            }
+        }
+    }
+    pub(crate) fn read(&mut self, buf: &mut [u8]) -> usize {
+        let position = self.position;
+        let available = &self.output()[position..];
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.position += n;
+        n
+    }
+    fn output(&self) -> &Vec<u8> {
+        match &self.decoder {
+            Decoder::Gzip(decoder) => decoder.get_ref(),
+            Decoder::Deflate(decoder) => decoder.get_ref(),
+            #[cfg(feature = "brotli")]
+            Decoder::Brotli(decoder) => decoder.get_ref(),
+        }
+    }
+    fn compact(&mut self) {
+        let output = match &mut self.decoder {
+            Decoder::Gzip(decoder) => decoder.get_mut(),
+            Decoder::Deflate(decoder) => decoder.get_mut(),
+            #[cfg(feature = "brotli")]
+            Decoder::Brotli(decoder) => decoder.get_mut(),
+        };
+        output.drain(..self.position);
+        self.position = 0;
+    }
+}
+
+/// Decodes a gzip compressed stream, e.g. a [`ResponseRead`](crate::ResponseRead) of a response with
+/// `Content-Encoding: gzip`.
+///
+/// Responses are only decoded automatically if the request has no `Accept-Encoding` header, so setting one opts out and
+/// allows decoding only the responses that should be, with this type or [`DeflateDecoder`].
+pub struct GzipDecoder<R> {
+    inner: DecodeReader<R>,
+}
+
+impl<R> GzipDecoder<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner: DecodeReader::new(inner, Decoder::Gzip(GzDecoder::new(Vec::new()))),
+        }
+    }
+    pub fn into_inner(self) -> R {
+        self.inner.inner
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for GzipDecoder<R> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        self.inner.poll_read(cx, buf)
+    }
+}
+
+/// Decodes a zlib wrapped deflate stream, as sent with `Content-Encoding: deflate`. See [`GzipDecoder`].
+pub struct DeflateDecoder<R> {
+    inner: DecodeReader<R>,
+}
+
+impl<R> DeflateDecoder<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner: DecodeReader::new(inner, Decoder::Deflate(ZlibDecoder::new(Vec::new()))),
+        }
+    }
+    pub fn into_inner(self) -> R {
+        self.inner.inner
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for DeflateDecoder<R> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        self.inner.poll_read(cx, buf)
+    }
+}
+
+// Feeds what is read from `inner` into the push based decoder. EOF of `inner` finishes the decoder, which fails if the
+// compressed stream is truncated.
+struct DecodeReader<R> {
+    inner: R,
+    decompression: Decompression,
+}
+
+impl<R> DecodeReader<R> {
+    fn new(inner: R, decoder: Decoder) -> Self {
+        Self {
+            inner,
+            decompression: Decompression::new(decoder),
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> DecodeReader<R> {
+    fn poll_read(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let mut chunk = [0u8; 4096];
+        loop {
+            let n = self.decompression.read(buf);
+            if n > 0 || buf.is_empty() || self.decompression.is_finished() {
+                return Poll::Ready(Ok(n));
+            }
+            let n = ready!(Pin::new(&mut self.inner).poll_read(cx, &mut chunk))?;
+            self.decompression.write(&chunk[..n])?;
+        }
+    }
+}
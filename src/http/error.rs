@@ -1,8 +1,10 @@
 use std::{io, sync::Arc};
 
-use http::{uri::Scheme, HeaderValue, Method};
+use http::{uri::Scheme, HeaderValue, Method, StatusCode, Uri, Version};
 use thiserror::Error;
 
+#[cfg(not(target_arch = "wasm32"))]
+use super::timeouts::TimeoutPhase;
 use crate::TransportError;
 
 #[derive(Error, Debug, Clone)]
@@ -16,9 +18,22 @@ pub enum HttpError {
     NetworkError,
     #[error("redirect")]
     Redirect,
+    #[error("invalid uri: {0}")]
+    InvalidUri(#[source] Arc<http::uri::InvalidUri>),
+    #[error("invalid request: {0}")]
+    InvalidRequest(#[source] Arc<http::Error>),
     #[cfg(not(target_arch = "wasm32"))]
-    #[error("missing host in URI or host header")]
-    MissingHost,
+    #[error("redirect to {0} blocked by policy")]
+    RedirectBlocked(Uri),
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("missing host in URI or host header: {0}")]
+    MissingHost(Uri),
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("invalid port: {0:?}")]
+    InvalidPort(String),
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("unsupported HTTP version: {0:?}")]
+    UnsupportedVersion(Version),
     #[cfg(not(target_arch = "wasm32"))]
     #[error("unexpected URI scheme: {0:?}")]
     UnexpectedScheme(Scheme),
@@ -27,16 +42,81 @@ pub enum HttpError {
     UnsupportedTransferEncoding(HeaderValue),
     #[cfg(not(target_arch = "wasm32"))]
     #[error("connect error: {0:?}")]
-    ConnectError(TransportError),
+    ConnectError(#[source] TransportError),
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("failed to resolve {host}: {error}")]
+    Dns {
+        host: String,
+        #[source]
+        error: Arc<io::Error>,
+    },
+    #[cfg(all(feature = "tls", not(target_arch = "wasm32")))]
+    #[error("tls handshake with {server_name} failed: {error}")]
+    TlsHandshake {
+        server_name: String,
+        #[source]
+        error: rustls::Error,
+    },
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("https requires the tls feature")]
+    TlsUnsupported,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("certificate of {server_name} has expired")]
+    CertificateExpired { server_name: String },
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("server selected unsupported protocol {0:?} through ALPN")]
+    UnexpectedAlpn(String),
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("trailers can only be sent with a chunked body")]
+    TrailersRequireChunked,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("server closed the connection without responding, it may require HTTP/2 (ALPN: {})", .alpn.as_deref().unwrap_or("none"))]
+    ProtocolMismatch { alpn: Option<String> },
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("server responded with {0} before the request body was sent")]
+    EarlyResponse(StatusCode),
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("server responded with {status}")]
+    Status { status: StatusCode, body: Vec<u8> },
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("proxy rejected the tunnel with status {0}")]
+    TunnelRejected(StatusCode),
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("connection is closed")]
+    ConnectionClosed,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("connection is busy with another request")]
+    ConnectionBusy,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("invalid response header {0:?}")]
+    InvalidHeader(String),
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("invalid chunked body: {0}")]
+    InvalidChunk(&'static str),
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("connection closed after {received} of {expected} body bytes")]
+    IncompleteBody { expected: u64, received: u64 },
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("response body was not read to the end")]
+    BodyNotDrained,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("{0:?} timeout elapsed")]
+    Timeout(TimeoutPhase),
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("request deadline exceeded")]
+    DeadlineExceeded,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("request was aborted")]
+    Aborted,
     #[cfg(not(target_arch = "wasm32"))]
     #[error("io error: {0:?}")]
-    IoError(Arc<io::Error>),
+    IoError(#[source] Arc<io::Error>),
     #[cfg(target_arch = "wasm32")]
     #[error("invalid url error: {0}")]
-    InvalidUrl(Arc<gloo_net::Error>),
+    InvalidUrl(#[source] Arc<gloo_net::Error>),
     #[cfg(target_arch = "wasm32")]
     #[error("unknown gloo error: {0}")]
-    Other(std::sync::Arc<gloo_net::Error>),
+    Other(#[source] std::sync::Arc<gloo_net::Error>),
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -46,6 +126,31 @@ impl From<gloo_net::Error> for HttpError {
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+impl From<TransportError> for HttpError {
+    fn from(value: TransportError) -> Self {
+        match value {
+            TransportError::Dns { host, error } => Self::Dns { host, error },
+            #[cfg(feature = "tls")]
+            TransportError::TlsHandshake { server_name, error } => Self::TlsHandshake { server_name, error },
+            TransportError::CertificateExpired { server_name } => Self::CertificateExpired { server_name },
+            TransportError::UnexpectedAlpn(alpn) => Self::UnexpectedAlpn(alpn),
+            err => Self::ConnectError(err),
+        }
+    }
+}
+
+// Recovers errors that went through `From<HttpError> for io::Error`, e.g. when reading a response body.
+#[cfg(not(target_arch = "wasm32"))]
+impl From<io::Error> for HttpError {
+    fn from(value: io::Error) -> Self {
+        match value.get_ref().and_then(|inner| inner.downcast_ref::<HttpError>()) {
+            Some(err) => err.clone(),
+            None => Self::IoError(Arc::new(value)),
+        }
+    }
+}
+
 impl From<HttpError> for io::Error {
     fn from(value: HttpError) -> Self {
         let kind = match &value {
@@ -56,19 +161,71 @@ impl From<HttpError> for io::Error {
             HttpError::InvalidHeaderValue(_) => io::ErrorKind::InvalidData,
             HttpError::InvalidMethod(_) => io::ErrorKind::InvalidData,
             HttpError::Redirect => io::ErrorKind::Unsupported,
+            HttpError::InvalidUri(_) => io::ErrorKind::InvalidInput,
+            HttpError::InvalidRequest(_) => io::ErrorKind::InvalidInput,
             #[cfg(target_arch = "wasm32")]
             HttpError::Other(_) => io::ErrorKind::Other,
             #[cfg(not(target_arch = "wasm32"))]
-            HttpError::MissingHost => io::ErrorKind::Unsupported,
+            HttpError::RedirectBlocked(_) => io::ErrorKind::PermissionDenied,
+            #[cfg(not(target_arch = "wasm32"))]
+            HttpError::MissingHost(_) => io::ErrorKind::Unsupported,
+            #[cfg(not(target_arch = "wasm32"))]
+            HttpError::InvalidPort(_) => io::ErrorKind::InvalidInput,
+            #[cfg(not(target_arch = "wasm32"))]
+            HttpError::UnsupportedVersion(_) => io::ErrorKind::Unsupported,
             #[cfg(not(target_arch = "wasm32"))]
             HttpError::UnexpectedScheme(_) => io::ErrorKind::Unsupported,
             #[cfg(not(target_arch = "wasm32"))]
             HttpError::ConnectError(err) => match err {
                 TransportError::InvalidDnsName(_) => io::ErrorKind::InvalidData,
+                TransportError::Dns { error, .. } => error.kind(),
                 TransportError::TcpConnect(err) => err.kind(),
                 TransportError::TlsConnect(err) => err.kind(),
+                #[cfg(feature = "tls")]
+                TransportError::TlsHandshake { .. } => io::ErrorKind::InvalidData,
+                TransportError::CertificateExpired { .. } => io::ErrorKind::InvalidData,
+                TransportError::TlsTimeout { .. } => io::ErrorKind::TimedOut,
+                TransportError::UnexpectedAlpn(_) => io::ErrorKind::Unsupported,
             },
             #[cfg(not(target_arch = "wasm32"))]
+            HttpError::Dns { error, .. } => error.kind(),
+            #[cfg(all(feature = "tls", not(target_arch = "wasm32")))]
+            HttpError::TlsHandshake { .. } => io::ErrorKind::InvalidData,
+            #[cfg(not(target_arch = "wasm32"))]
+            HttpError::TlsUnsupported => io::ErrorKind::Unsupported,
+            #[cfg(not(target_arch = "wasm32"))]
+            HttpError::CertificateExpired { .. } => io::ErrorKind::InvalidData,
+            #[cfg(not(target_arch = "wasm32"))]
+            HttpError::UnexpectedAlpn(_) => io::ErrorKind::Unsupported,
+            #[cfg(not(target_arch = "wasm32"))]
+            HttpError::TrailersRequireChunked => io::ErrorKind::InvalidInput,
+            #[cfg(not(target_arch = "wasm32"))]
+            HttpError::ProtocolMismatch { .. } => io::ErrorKind::ConnectionReset,
+            #[cfg(not(target_arch = "wasm32"))]
+            HttpError::EarlyResponse(_) => io::ErrorKind::ConnectionAborted,
+            #[cfg(not(target_arch = "wasm32"))]
+            HttpError::Status { .. } => io::ErrorKind::Other,
+            #[cfg(not(target_arch = "wasm32"))]
+            HttpError::TunnelRejected(_) => io::ErrorKind::ConnectionRefused,
+            #[cfg(not(target_arch = "wasm32"))]
+            HttpError::ConnectionClosed => io::ErrorKind::NotConnected,
+            #[cfg(not(target_arch = "wasm32"))]
+            HttpError::ConnectionBusy => io::ErrorKind::WouldBlock,
+            #[cfg(not(target_arch = "wasm32"))]
+            HttpError::InvalidHeader(_) => io::ErrorKind::InvalidData,
+            #[cfg(not(target_arch = "wasm32"))]
+            HttpError::InvalidChunk(_) => io::ErrorKind::InvalidData,
+            #[cfg(not(target_arch = "wasm32"))]
+            HttpError::IncompleteBody { .. } => io::ErrorKind::UnexpectedEof,
+            #[cfg(not(target_arch = "wasm32"))]
+            HttpError::BodyNotDrained => io::ErrorKind::InvalidInput,
+            #[cfg(not(target_arch = "wasm32"))]
+            HttpError::Timeout(_) => io::ErrorKind::TimedOut,
+            #[cfg(not(target_arch = "wasm32"))]
+            HttpError::DeadlineExceeded => io::ErrorKind::TimedOut,
+            #[cfg(not(target_arch = "wasm32"))]
+            HttpError::Aborted => io::ErrorKind::ConnectionAborted,
+            #[cfg(not(target_arch = "wasm32"))]
             HttpError::IoError(err) => err.kind(),
             #[cfg(not(target_arch = "wasm32"))]
             HttpError::UnsupportedTransferEncoding(_) => io::ErrorKind::Unsupported,
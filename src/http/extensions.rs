@@ -0,0 +1,79 @@
+use std::{
+    net::SocketAddr,
+    time::{Duration, SystemTime},
+};
+
+#[cfg(feature = "cache")]
+use crate::CacheStatus;
+use crate::{AddressFamily, ContentRange};
+
+use super::common::parse_http_date;
+#[cfg(feature = "tls")]
+use crate::TlsInfo;
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct PeerAddr(pub(crate) SocketAddr);
+
+#[derive(Clone, Debug)]
+pub(crate) struct RequestBytes(pub(crate) Vec<u8>);
+
+/// Accessors for connection details that are stored in the extensions of responses.
+pub trait ResponseExt {
+    /// The address the request was actually sent to.
+    fn peer_addr(&self) -> Option<SocketAddr>;
+    /// Whether the request was sent over IPv4 or IPv6, derived from [`peer_addr`](ResponseExt::peer_addr).
+    fn address_family(&self) -> Option<AddressFamily>;
+    /// The negotiated TLS parameters, if the request was sent over TLS.
+    #[cfg(feature = "tls")]
+    fn tls_info(&self) -> Option<TlsInfo>;
+    /// The exact bytes of the request head and body as written to the connection, if capturing them was enabled with
+    /// [`RequestSend::capture_request_bytes`](crate::RequestSend::capture_request_bytes).
+    fn request_bytes(&self) -> Option<&[u8]>;
+    /// Whether the server answered a conditional request with `304 Not Modified`. Such responses never have a body.
+    fn is_not_modified(&self) -> bool;
+    /// The range of the full representation that the body holds, if the server answered a range request with
+    /// `206 Partial Content`.
+    fn content_range(&self) -> Option<ContentRange>;
+    /// How long the server asks to wait before retrying, typically with `429 Too Many Requests` or
+    /// `503 Service Unavailable`. A `Retry-After` date in the past gives a zero duration.
+    fn retry_after(&self) -> Option<Duration>;
+    /// Whether a response returned by [`Client::send_cached`](crate::Client::send_cached) came from the cache.
+    #[cfg(feature = "cache")]
+    fn cache_status(&self) -> Option<CacheStatus>;
+}
+
+impl<T> ResponseExt for http::Response<T> {
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.extensions().get::<PeerAddr>().map(|addr| addr.0)
+    }
+    fn address_family(&self) -> Option<AddressFamily> {
+        self.peer_addr().map(AddressFamily::from)
+    }
+    #[cfg(feature = "tls")]
+    fn tls_info(&self) -> Option<TlsInfo> {
+        self.extensions().get::<TlsInfo>().copied()
+    }
+    fn request_bytes(&self) -> Option<&[u8]> {
+        self.extensions().get::<RequestBytes>().map(|bytes| bytes.0.as_slice())
+    }
+    fn is_not_modified(&self) -> bool {
+        self.status() == http::StatusCode::NOT_MODIFIED
+    }
+    fn content_range(&self) -> Option<ContentRange> {
+        if self.status() != http::StatusCode::PARTIAL_CONTENT {
+            return None;
+        }
+        ContentRange::parse(self.headers().get(http::header::CONTENT_RANGE)?)
+    }
+    fn retry_after(&self) -> Option<Duration> {
+        let value = self.headers().get(http::header::RETRY_AFTER)?.to_str().ok()?.trim();
+        if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) {
+            return value.parse().ok().map(Duration::from_secs);
+        }
+        Some(parse_http_date(value)?.duration_since(SystemTime::now()).unwrap_or_default())
+    }
+    #[cfg(feature = "cache")]
+    fn cache_status(&self) -> Option<CacheStatus> {
+        self.extensions().get::<CacheStatus>().copied()
+    }
+}
@@ -0,0 +1,17 @@
+use http::{header, HeaderValue, Method, Request, Uri};
+
+/// Builds a request with the pairs encoded as an `application/x-www-form-urlencoded` body.
+pub fn form_request<K: AsRef<str>, V: AsRef<str>>(method: Method, uri: Uri, pairs: &[(K, V)]) -> Request<Vec<u8>> {
+    let body = form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(pairs.iter().map(|(k, v)| (k.as_ref(), v.as_ref())))
+        .finish()
+        .into_bytes();
+    let mut request = Request::new(Vec::new());
+    *request.method_mut() = method;
+    *request.uri_mut() = uri;
+    let headers = request.headers_mut();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/x-www-form-urlencoded"));
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(body.len()));
+    *request.body_mut() = body;
+    request
+}
@@ -0,0 +1,35 @@
+use std::net::IpAddr;
+
+use http::{header, HeaderMap, HeaderName, HeaderValue, Request};
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// Records `client` as the address the request is forwarded for, in both the `Forwarded` and the `X-Forwarded-For`
+/// header.
+///
+/// Both headers are lists with the original client first, so the address is appended to values set by earlier proxies.
+/// Multiple header lines are combined into one, which is equivalent for list-valued headers.
+pub fn with_forwarded_for<T>(mut request: Request<T>, client: IpAddr) -> Request<T> {
+    // IPv6 addresses contain `:`, so `Forwarded` needs them bracketed and quoted.
+    let forwarded = match client {
+        IpAddr::V4(addr) => format!("for={addr}"),
+        IpAddr::V6(addr) => format!("for=\"[{addr}]\""),
+    };
+    append_to_list(request.headers_mut(), header::FORWARDED, &forwarded);
+    append_to_list(request.headers_mut(), X_FORWARDED_FOR, &client.to_string());
+    request
+}
+
+fn append_to_list(headers: &mut HeaderMap, name: HeaderName, element: &str) {
+    let mut value = Vec::new();
+    for existing in headers.get_all(&name) {
+        let existing = existing.as_bytes().trim_ascii();
+        if !existing.is_empty() {
+            value.extend_from_slice(existing);
+            value.extend_from_slice(b", ");
+        }
+    }
+    value.extend_from_slice(element.as_bytes());
+    // Existing values are valid header values and the element is ASCII, so the combination is valid as well.
+    headers.insert(name, HeaderValue::from_bytes(&value).unwrap());
+}
@@ -0,0 +1,34 @@
+use std::time::Duration;
+
+use http::HeaderMap;
+
+/// Parameters of a response's `Keep-Alive` header.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KeepAlive {
+    /// How long the server keeps the idle connection open.
+    pub timeout: Option<Duration>,
+    /// How many more requests the server accepts on the connection.
+    pub max: Option<u32>,
+}
+
+impl KeepAlive {
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let mut keep_alive = None;
+        for param in headers
+            .get_all("keep-alive")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+        {
+            let keep_alive = keep_alive.get_or_insert_with(Self::default);
+            let Some((name, value)) = param.split_once('=') else { continue };
+            let value = value.trim().trim_matches('"');
+            match name.trim() {
+                name if name.eq_ignore_ascii_case("timeout") => keep_alive.timeout = value.parse().ok().map(Duration::from_secs),
+                name if name.eq_ignore_ascii_case("max") => keep_alive.max = value.parse().ok(),
+                _ => {}
+            }
+        }
+        keep_alive
+    }
+}
@@ -0,0 +1,60 @@
+use std::sync::Arc;
+
+use http::{Method, Request, Response, Uri};
+
+use super::error::HttpError;
+use super::{RequestSend, ResponseRead};
+
+pub async fn get<U: TryInto<Uri>>(uri: U) -> Result<Response<ResponseRead>, HttpError>
+where
+    U::Error: Into<http::Error>,
+{
+    send(Method::GET, uri, Vec::new()).await
+}
+
+pub async fn head<U: TryInto<Uri>>(uri: U) -> Result<Response<ResponseRead>, HttpError>
+where
+    U::Error: Into<http::Error>,
+{
+    send(Method::HEAD, uri, Vec::new()).await
+}
+
+pub async fn delete<U: TryInto<Uri>>(uri: U) -> Result<Response<ResponseRead>, HttpError>
+where
+    U::Error: Into<http::Error>,
+{
+    send(Method::DELETE, uri, Vec::new()).await
+}
+
+pub async fn post<U: TryInto<Uri>>(uri: U, body: impl Into<Vec<u8>>) -> Result<Response<ResponseRead>, HttpError>
+where
+    U::Error: Into<http::Error>,
+{
+    send(Method::POST, uri, body.into()).await
+}
+
+pub async fn put<U: TryInto<Uri>>(uri: U, body: impl Into<Vec<u8>>) -> Result<Response<ResponseRead>, HttpError>
+where
+    U::Error: Into<http::Error>,
+{
+    send(Method::PUT, uri, body.into()).await
+}
+
+pub async fn patch<U: TryInto<Uri>>(uri: U, body: impl Into<Vec<u8>>) -> Result<Response<ResponseRead>, HttpError>
+where
+    U::Error: Into<http::Error>,
+{
+    send(Method::PATCH, uri, body.into()).await
+}
+
+async fn send<U: TryInto<Uri>>(method: Method, uri: U, body: Vec<u8>) -> Result<Response<ResponseRead>, HttpError>
+where
+    U::Error: Into<http::Error>,
+{
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .body(body)
+        .map_err(|err| HttpError::InvalidRequest(Arc::new(err)))?;
+    RequestSend::new(&request).await
+}
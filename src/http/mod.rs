@@ -4,12 +4,54 @@ use std::{
     task::{Context, Poll},
 };
 
-use async_http_codec::BodyDecodeState;
 use futures::{future::FusedFuture, ready, AsyncRead, Future};
 
 use crate::Transport;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::abort::AbortHandle;
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::builder::RequestBuilder;
+#[cfg(all(feature = "cache", not(target_arch = "wasm32")))]
+pub use self::cache::{CacheStatus, CacheStorage, CachedResponse, MemoryCache};
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::capabilities::{options, server_options, Capabilities, Cors};
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::client::Client;
+pub use self::common::remove_connection_headers;
+pub use self::conditional::with_etag;
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::connection::{Connection, ReusePredicate};
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::credentials::{BearerToken, CredentialProvider};
+#[cfg(all(feature = "decompression", not(target_arch = "wasm32")))]
+pub use self::decompression::{DeflateDecoder, GzipDecoder};
 pub use self::error::HttpError;
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::extensions::ResponseExt;
+pub use self::form::form_request;
+pub use self::forwarded::with_forwarded_for;
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::keep_alive::KeepAlive;
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::methods::{delete, get, head, patch, post, put};
+pub use self::multipart::{Multipart, MultipartWriter};
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::observer::Observer;
+pub use self::range::{with_range, ContentRange};
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::redirect::RedirectPolicy;
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::request_native::{DuplexResponse, DuplexWrite, RequestWrite};
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::schemes::SchemeConfig;
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::timeouts::{Deadline, TimeoutPhase, Timeouts};
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::tunnel::connect_tunnel;
+#[cfg(all(feature = "tls", not(target_arch = "wasm32")))]
+pub use self::tunnel::connect_tunnel_with_client_config;
+pub use self::url::UrlBuilder;
 
 #[cfg(target_arch = "wasm32")]
 mod request_wasm;
@@ -25,11 +67,56 @@ mod request_native;
 #[cfg(not(target_arch = "wasm32"))]
 type ResponseReadInner = response_native::ResponseRead;
 
+#[cfg(not(target_arch = "wasm32"))]
+mod abort;
+#[cfg(not(target_arch = "wasm32"))]
+mod builder;
+#[cfg(all(feature = "cache", not(target_arch = "wasm32")))]
+mod cache;
+#[cfg(not(target_arch = "wasm32"))]
+mod capabilities;
+#[cfg(not(target_arch = "wasm32"))]
+mod chunked;
+#[cfg(not(target_arch = "wasm32"))]
+mod client;
 mod common;
+mod conditional;
+#[cfg(not(target_arch = "wasm32"))]
+mod connection;
+#[cfg(not(target_arch = "wasm32"))]
+mod credentials;
+#[cfg(all(feature = "decompression", not(target_arch = "wasm32")))]
+mod decompression;
 mod error;
+#[cfg(not(target_arch = "wasm32"))]
+mod extensions;
+mod form;
+mod forwarded;
+#[cfg(not(target_arch = "wasm32"))]
+mod keep_alive;
+#[cfg(not(target_arch = "wasm32"))]
+mod methods;
+mod multipart;
+#[cfg(not(target_arch = "wasm32"))]
+mod observer;
+mod range;
+#[cfg(not(target_arch = "wasm32"))]
+mod redirect;
+#[cfg(not(target_arch = "wasm32"))]
+mod schemes;
+#[cfg(not(target_arch = "wasm32"))]
+mod timeouts;
+#[cfg(not(target_arch = "wasm32"))]
+mod tunnel;
+mod url;
 
+/// Future that sends a request and resolves to the response head, with the body readable from [`ResponseRead`].
+///
+/// Dropping it before it resolves closes the connection, whatever phase the request was in.
 pub struct RequestSend<'a> {
     inner: request_native::RequestSend<'a>,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
 }
 
 impl RequestSend<'_> {
@@ -39,13 +126,196 @@ impl RequestSend<'_> {
         #[cfg(not(target_arch = "wasm32"))]
         {
             let inner = request_native::RequestSend::new(request);
-            RequestSend { inner }
+            RequestSend {
+                inner,
+                #[cfg(feature = "tracing")]
+                span: Self::span(request),
+            }
         }
     }
-    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg(all(feature = "tls", not(target_arch = "wasm32")))]
     pub fn new_with_client_config(request: &http::Request<impl AsRef<[u8]>>, client_config: std::sync::Arc<rustls::ClientConfig>) -> RequestSend<'_> {
+        Self::new_with(request, client_config)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn new_with(request: &http::Request<impl AsRef<[u8]>>, client_config: std::sync::Arc<crate::ClientConfig>) -> RequestSend<'_> {
         let inner = request_native::RequestSend::new_with_client_config(request, client_config);
-        RequestSend { inner }
+        RequestSend {
+            inner,
+            #[cfg(feature = "tracing")]
+            span: Self::span(request),
+        }
+    }
+    /// Takes ownership of the request, so the returned future isn't tied to its lifetime and can e.g. be spawned as a task.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new_owned(request: http::Request<impl Into<Vec<u8>>>) -> RequestSend<'static> {
+        #[cfg(feature = "tracing")]
+        let span = Self::span(&request);
+        RequestSend {
+            inner: request_native::RequestSend::new_owned(request),
+            #[cfg(feature = "tracing")]
+            span,
+        }
+    }
+    /// Connects to `addr` instead of resolving the host of the request, validating the certificate against `server_name`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn on_addr(request: &http::Request<impl AsRef<[u8]>>, addr: std::net::SocketAddr, server_name: impl Into<String>) -> RequestSend<'_> {
+        let inner = request_native::RequestSend::on_addr(request, addr, server_name.into());
+        RequestSend {
+            inner,
+            #[cfg(feature = "tracing")]
+            span: Self::span(request),
+        }
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn with_transport(request: &http::Request<impl AsRef<[u8]>>, transport: Transport) -> RequestSend<'_> {
+        let inner = request_native::RequestSend::with_transport(request, transport);
+        RequestSend {
+            inner,
+            #[cfg(feature = "tracing")]
+            span: Self::span(request),
+        }
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_version(mut self, version: http::Version) -> Self {
+        self.inner.options_mut().version = version;
+        self
+    }
+    /// Sends `Connection: close`, so neither side keeps the connection open after the response.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn close_connection(mut self) -> Self {
+        self.inner.options_mut().close_connection = true;
+        self
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_schemes(mut self, schemes: SchemeConfig) -> Self {
+        self.inner.options_mut().schemes = schemes;
+        self
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.inner.options_mut().timeouts = timeouts;
+        self
+    }
+    /// Fails the request with [`HttpError::DeadlineExceeded`] if it, including reading the body, isn't complete by
+    /// `deadline`. Applies in addition to the per-phase [`Timeouts`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_deadline(mut self, deadline: Deadline) -> Self {
+        self.inner.options_mut().deadline = Some(deadline);
+        self
+    }
+    /// Makes the request fail with [`HttpError::Aborted`] once `handle` is aborted, see [`AbortHandle`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_abort_handle(mut self, handle: AbortHandle) -> Self {
+        self.inner.options_mut().abort = Some(handle);
+        self
+    }
+    /// Enables TCP keepalive probes after the connection has been idle for `time`, so that middleboxes like NATs don't
+    /// silently drop it. Only the idle time is configured, the probe interval and count stay at the system defaults.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_tcp_keepalive(mut self, time: std::time::Duration) -> Self {
+        self.inner.options_mut().tcp_keepalive = Some(time);
+        self
+    }
+    /// Connects to the host and port of the `Host` header instead of the authority of the URI, e.g. to route a request
+    /// through a proxy-style setup where the two differ.
+    ///
+    /// By default the URI decides where to connect and the `Host` header is only sent. With this option the header takes
+    /// precedence if it is a valid `host[:port]`, otherwise the URI is used. The scheme of the URI still decides about TLS
+    /// and provides the default port.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn connect_to_host_header(mut self) -> Self {
+        self.inner.options_mut().connect_to_host_header = true;
+        self
+    }
+    /// Keeps a copy of the request head and body as they are written, for debugging requests that a server rejects.
+    ///
+    /// The bytes are available through [`ResponseExt::request_bytes`] and are also logged with the `tracing` feature.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn capture_request_bytes(mut self) -> Self {
+        self.inner.options_mut().capture_request_bytes = true;
+        self
+    }
+    /// Fails with [`HttpError::Status`] if the server responds with a 4xx or 5xx status, instead of returning the response.
+    ///
+    /// Up to 64 KiB of the body are read into the error, the rest is discarded together with the connection.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn error_for_status(mut self) -> Self {
+        self.inner.options_mut().error_for_status = true;
+        self
+    }
+    /// Calls `should_read_body` with the response head as soon as it arrives. If it returns `false`, the body is skipped:
+    /// it reads as empty and the connection is closed instead of reused, unless the body was empty anyway.
+    ///
+    /// With [`error_for_status`](Self::error_for_status), a skipped error body leaves the body of the error empty.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn should_read_body(mut self, should_read_body: impl Fn(&http::Response<()>) -> bool + Send + Sync + 'static) -> Self {
+        self.inner.options_mut().should_read_body = Some(std::sync::Arc::new(should_read_body));
+        self
+    }
+    /// Fails with [`HttpError::InvalidHeader`] if a response header contains bytes that aren't allowed by the spec. By
+    /// default such header lines are dropped, since some servers send them and the rest of the response is usually fine.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn strict_headers(mut self) -> Self {
+        self.inner.options_mut().strict_headers = true;
+        self
+    }
+    /// Doesn't add a `Content-Length` header when the request has none, leaving the framing headers entirely to the caller.
+    ///
+    /// The body is still written as is. Without framing headers the server can't know where the body ends, so unless the
+    /// request has no body, e.g. a `GET`, it may wait for more body bytes that never come.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn without_content_length(mut self) -> Self {
+        self.inner.options_mut().omit_content_length = true;
+        self
+    }
+    /// Writes `headers` verbatim and in the given order instead of the headers of the request, for servers that are
+    /// sensitive to the casing or order of header names.
+    ///
+    /// `Host`, and `Content-Length` unless disabled, are appended if no raw header has the same name. Nothing else is added,
+    /// so responses aren't decompressed automatically, and `with_gzip_body` has no effect.
+    /// A raw `Transfer-Encoding` header requires the body to be encoded accordingly by the caller.
+    #[cfg(feature = "raw-headers")]
+    pub fn with_raw_headers(mut self, headers: Vec<(String, String)>) -> Self {
+        self.inner.options_mut().raw_headers = Some(headers);
+        self
+    }
+    /// Sends `target` as the request target instead of the path and query of the URI, e.g. `*` for `OPTIONS *`.
+    ///
+    /// The target `*` is only allowed with `OPTIONS`, other methods fail with [`HttpError::InvalidMethod`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_request_target(mut self, target: http::Uri) -> Self {
+        self.inner.options_mut().target = Some(target);
+        self
+    }
+    /// Compresses the body with gzip and sets `Content-Encoding: gzip`, unless the request already has a `Content-Encoding`.
+    #[cfg(feature = "compression")]
+    pub fn with_gzip_body(mut self) -> Self {
+        self.inner.options_mut().gzip_body = true;
+        self
+    }
+    /// Resolves to the response head separately from its body, in the same shape as `RequestWrite::response`.
+    ///
+    /// This suits callers that decide from the status and headers whether to read the body at all. Dropping the body
+    /// without reading it closes the connection.
+    pub async fn response(self) -> Result<(http::Response<()>, ResponseRead), HttpError> {
+        let (parts, body) = self.await?.into_parts();
+        Ok((http::Response::from_parts(parts, ()), body))
+    }
+    #[cfg(feature = "tracing")]
+    fn span<T>(request: &http::Request<T>) -> tracing::Span {
+        use tracing::field::Empty;
+        tracing::debug_span!("http_request", method = %request.method(), host = Empty, port = Empty, status = Empty)
+    }
+}
+
+impl<'a> RequestSend<'a> {
+    /// Encodes the request head into `buffer` instead of a newly allocated one, so that clients sending many requests can
+    /// reuse a single allocation. Once the head is sent, `buffer` holds it.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_head_buffer(mut self, buffer: &'a mut Vec<u8>) -> Self {
+        self.inner.set_head_buffer(buffer);
+        self
     }
 }
 
@@ -53,6 +323,10 @@ impl Future for RequestSend<'_> {
     type Output = Result<http::Response<ResponseRead>, HttpError>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        #[cfg(feature = "tracing")]
+        let span = self.span.clone();
+        #[cfg(feature = "tracing")]
+        let _enter = span.enter();
         let response = ready!(self.inner.poll(cx))?;
         Ok(response.map(|inner| ResponseRead { inner })).into()
     }
@@ -69,9 +343,66 @@ pub struct ResponseRead {
 }
 
 impl ResponseRead {
-    pub(crate) fn into_inner(self) -> Result<(BodyDecodeState, Transport), HttpError> {
+    pub(crate) fn into_inner(self) -> Result<Transport, HttpError> {
         self.inner.into_inner()
     }
+    /// Whether the server intends to keep the connection open after this response.
+    ///
+    /// HTTP/1.1 connections are kept alive unless the server sent `Connection: close`, while HTTP/1.0 connections are only
+    /// kept alive if the server sent `Connection: keep-alive`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn is_keep_alive(&self) -> bool {
+        self.inner.is_keep_alive()
+    }
+    /// The idle timeout and remaining request budget from the `Keep-Alive` header, if the server sent one.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn keep_alive_hint(&self) -> Option<KeepAlive> {
+        self.inner.keep_alive_hint()
+    }
+    /// Reads the rest of the body, discarding it, and returns the trailer fields of a chunked body.
+    ///
+    /// The map is empty if the response had no trailers.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn trailers(&mut self) -> Result<http::HeaderMap, HttpError> {
+        futures::io::copy(&mut *self, &mut futures::io::sink()).await?;
+        Ok(self.inner.trailers())
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn into_reusable(self) -> Result<Option<Transport>, HttpError> {
+        self.inner.into_reusable()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ResponseRead {
+    /// Reads the body line by line, without the line endings.
+    pub fn lines(self) -> impl futures::Stream<Item = Result<String, HttpError>> + Unpin {
+        use futures::{AsyncBufReadExt, TryStreamExt};
+        AsyncBufReadExt::lines(self).map_err(HttpError::from)
+    }
+    /// Appends the rest of the body to `buf` and returns the number of bytes read.
+    ///
+    /// `buf` is not cleared first, so a buffer can be cleared and reused across responses to avoid allocating for each body.
+    pub async fn read_into(&mut self, buf: &mut Vec<u8>) -> Result<usize, HttpError> {
+        use futures::AsyncReadExt;
+        Ok(self.read_to_end(buf).await?)
+    }
+    /// Writes the rest of the body into `sink`, flushes it and returns the number of bytes copied.
+    pub async fn copy_to<W: futures::AsyncWrite + Unpin>(mut self, mut sink: W) -> Result<u64, HttpError> {
+        use futures::{AsyncReadExt, AsyncWriteExt};
+        let mut buf = vec![0u8; 16 * 1024];
+        let mut copied = 0;
+        loop {
+            let n = self.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            sink.write_all(&buf[..n]).await?;
+            copied += n as u64;
+        }
+        sink.flush().await?;
+        Ok(copied)
+    }
 }
 
 impl AsyncRead for ResponseRead {
@@ -79,3 +410,13 @@ impl AsyncRead for ResponseRead {
         Pin::new(&mut self.inner).poll_read(cx, buf)
     }
 }
+
+#[cfg(not(target_arch = "wasm32"))]
+impl futures::AsyncBufRead for ResponseRead {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        Pin::new(&mut self.get_mut().inner).poll_fill_buf(cx)
+    }
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        Pin::new(&mut self.inner).consume(amt)
+    }
+}
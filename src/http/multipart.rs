@@ -0,0 +1,139 @@
+use std::io;
+
+use futures::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use http::{header, HeaderValue, Method, Request, Uri};
+
+/// Builder for `multipart/form-data` bodies.
+///
+/// Parts added here are buffered. Use [`into_writer`](Multipart::into_writer) to stream further parts, such as large
+/// files, into any [`AsyncWrite`] like a request body writer.
+#[derive(Clone, Debug)]
+pub struct Multipart {
+    boundary: String,
+    body: Vec<u8>,
+}
+
+impl Multipart {
+    pub fn new() -> Self {
+        let boundary = format!(
+            "async-web-client-{}",
+            std::iter::repeat_with(fastrand::alphanumeric).take(24).collect::<String>()
+        );
+        Self { boundary, body: Vec::new() }
+    }
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+    /// The `Content-Type` header value, including the boundary.
+    pub fn content_type(&self) -> HeaderValue {
+        HeaderValue::try_from(format!("multipart/form-data; boundary={}", self.boundary)).unwrap()
+    }
+    pub fn text(mut self, name: &str, value: &str) -> Self {
+        part_head(&mut self.body, &self.boundary, name, None);
+        self.body.extend_from_slice(value.as_bytes());
+        self.body.extend_from_slice(b"\r\n");
+        self
+    }
+    pub fn file(mut self, name: &str, filename: &str, content_type: &str, data: impl AsRef<[u8]>) -> Self {
+        part_head(&mut self.body, &self.boundary, name, Some((filename, content_type)));
+        self.body.extend_from_slice(data.as_ref());
+        self.body.extend_from_slice(b"\r\n");
+        self
+    }
+    /// Closes the body and builds a request with `Content-Type` and `Content-Length` set.
+    pub fn into_request(mut self, method: Method, uri: Uri) -> Request<Vec<u8>> {
+        let content_type = self.content_type();
+        close(&mut self.body, &self.boundary);
+        let mut request = Request::new(Vec::new());
+        *request.method_mut() = method;
+        *request.uri_mut() = uri;
+        let headers = request.headers_mut();
+        headers.insert(header::CONTENT_TYPE, content_type);
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from(self.body.len()));
+        *request.body_mut() = self.body;
+        request
+    }
+    /// Continues the body on `sink`, starting with the parts buffered so far.
+    pub fn into_writer<W: AsyncWrite + Unpin>(self, sink: W) -> MultipartWriter<W> {
+        MultipartWriter {
+            sink,
+            boundary: self.boundary,
+            pending: self.body,
+        }
+    }
+}
+
+impl Default for Multipart {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Streams `multipart/form-data` parts into a writer. Created by [`Multipart::into_writer`].
+pub struct MultipartWriter<W> {
+    sink: W,
+    boundary: String,
+    pending: Vec<u8>,
+}
+
+impl<W: AsyncWrite + Unpin> MultipartWriter<W> {
+    pub async fn text(&mut self, name: &str, value: &str) -> io::Result<()> {
+        part_head(&mut self.pending, &self.boundary, name, None);
+        self.pending.extend_from_slice(value.as_bytes());
+        self.pending.extend_from_slice(b"\r\n");
+        self.write_pending().await
+    }
+    /// Copies `data` to the sink as a file part and returns the number of bytes copied.
+    pub async fn file(&mut self, name: &str, filename: &str, content_type: &str, data: impl AsyncRead) -> io::Result<u64> {
+        part_head(&mut self.pending, &self.boundary, name, Some((filename, content_type)));
+        self.write_pending().await?;
+        let n = futures::io::copy(data, &mut self.sink).await?;
+        self.pending.extend_from_slice(b"\r\n");
+        Ok(n)
+    }
+    /// Writes the closing delimiter, flushes and returns the sink.
+    pub async fn finish(mut self) -> io::Result<W> {
+        close(&mut self.pending, &self.boundary);
+        self.write_pending().await?;
+        self.sink.flush().await?;
+        Ok(self.sink)
+    }
+    async fn write_pending(&mut self) -> io::Result<()> {
+        self.sink.write_all(&self.pending).await?;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+fn part_head(out: &mut Vec<u8>, boundary: &str, name: &str, file: Option<(&str, &str)>) {
+    out.extend_from_slice(b"--");
+    out.extend_from_slice(boundary.as_bytes());
+    out.extend_from_slice(b"\r\nContent-Disposition: form-data; name=\"");
+    escape(out, name);
+    out.push(b'"');
+    if let Some((filename, content_type)) = file {
+        out.extend_from_slice(b"; filename=\"");
+        escape(out, filename);
+        out.extend_from_slice(b"\"\r\nContent-Type: ");
+        out.extend(content_type.bytes().filter(|b| !matches!(b, b'\r' | b'\n')));
+    }
+    out.extend_from_slice(b"\r\n\r\n");
+}
+
+fn close(out: &mut Vec<u8>, boundary: &str) {
+    out.extend_from_slice(b"--");
+    out.extend_from_slice(boundary.as_bytes());
+    out.extend_from_slice(b"--\r\n");
+}
+
+// Same escaping browsers apply to names and filenames in form submissions.
+fn escape(out: &mut Vec<u8>, value: &str) {
+    for b in value.bytes() {
+        match b {
+            b'"' => out.extend_from_slice(b"%22"),
+            b'\r' => out.extend_from_slice(b"%0D"),
+            b'\n' => out.extend_from_slice(b"%0A"),
+            b => out.push(b),
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use http::{StatusCode, Uri};
+
+use super::error::HttpError;
+
+/// Callbacks for the lifecycle of requests sent through a [`Client`](crate::Client).
+///
+/// Every callback receives the time elapsed since the request started connecting. All methods default to doing nothing.
+pub trait Observer: Send + Sync {
+    fn on_connect_start(&self, _uri: &Uri) {}
+    fn on_connect_end(&self, _uri: &Uri, _elapsed: Duration, _result: Result<(), &HttpError>) {}
+    fn on_head_sent(&self, _uri: &Uri, _elapsed: Duration) {}
+    fn on_response_head(&self, _uri: &Uri, _status: StatusCode, _elapsed: Duration) {}
+    fn on_body_complete(&self, _uri: &Uri, _status: StatusCode, _elapsed: Duration) {}
+}
+
+pub(crate) struct BodyObserver {
+    observer: Arc<dyn Observer>,
+    uri: Uri,
+    status: StatusCode,
+    started: Instant,
+}
+
+impl BodyObserver {
+    pub(crate) fn new(observer: Arc<dyn Observer>, uri: Uri, status: StatusCode, started: Instant) -> Self {
+        Self {
+            observer,
+            uri,
+            status,
+            started,
+        }
+    }
+    pub(crate) fn complete(self) {
+        self.observer.on_body_complete(&self.uri, self.status, self.started.elapsed())
+    }
+}
@@ -0,0 +1,135 @@
+use http::{header, HeaderValue, Request};
+#[cfg(not(target_arch = "wasm32"))]
+use http::{Method, StatusCode};
+
+#[cfg(not(target_arch = "wasm32"))]
+use super::{client::Client, error::HttpError, ResponseExt};
+
+/// Sets the `Range` header to request the bytes from `start` to `end` (inclusive) of the body, or to the end of the body
+/// if `end` is `None`.
+///
+/// Servers that support ranges answer with `206 Partial Content`, see
+/// [`ResponseExt::content_range`](crate::ResponseExt::content_range). Others ignore the header and send the whole body.
+pub fn with_range<T>(mut request: Request<T>, start: u64, end: Option<u64>) -> Request<T> {
+    let value = match end {
+        Some(end) => format!("bytes={start}-{end}"),
+        None => format!("bytes={start}-"),
+    };
+    request.headers_mut().insert(header::RANGE, HeaderValue::try_from(value).unwrap());
+    request
+}
+
+/// A parsed `Content-Range` header of a `206 Partial Content` response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ContentRange {
+    /// Offset of the first byte of the body within the full representation.
+    pub start: u64,
+    /// Offset of the last byte of the body, inclusive.
+    pub end: u64,
+    /// Length of the full representation, if the server knows it.
+    pub complete_length: Option<u64>,
+}
+
+impl ContentRange {
+    /// Parses a `bytes start-end/length` header value, where the length may be `*`.
+    pub fn parse(value: &HeaderValue) -> Option<Self> {
+        let value = value.to_str().ok()?.trim();
+        let (unit, rest) = value.split_once(' ')?;
+        if !unit.eq_ignore_ascii_case("bytes") {
+            return None;
+        }
+        let (range, complete_length) = rest.trim_start().split_once('/')?;
+        let (start, end) = range.split_once('-')?;
+        let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+        let complete_length = match complete_length {
+            "*" => None,
+            length => Some(length.parse().ok()?),
+        };
+        if end < start || complete_length.is_some_and(|length| end >= length) {
+            return None;
+        }
+        Some(Self { start, end, complete_length })
+    }
+}
+
+// Upper bound for the number of concurrent range requests, however many segments are asked for.
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_SEGMENTS: usize = 32;
+
+// Downloads `request` in `segments` concurrent range requests, or with a single `GET` if the server doesn't announce range
+// support for it in a `HEAD` response. Ranges refer to the encoded body, so they are requested without content coding.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn download_ranges<T>(client: &Client, request: &Request<T>, segments: usize) -> Result<Vec<u8>, HttpError> {
+    let mut request = {
+        let mut owned = Request::new(Vec::new());
+        *owned.uri_mut() = request.uri().clone();
+        *owned.version_mut() = request.version();
+        *owned.headers_mut() = request.headers().clone();
+        owned
+    };
+    request
+        .headers_mut()
+        .insert(header::ACCEPT_ENCODING, HeaderValue::from_static("identity"));
+    *request.method_mut() = Method::HEAD;
+    let head = client.send(&request).await?;
+    *request.method_mut() = Method::GET;
+    let accepts_ranges = head
+        .headers()
+        .get_all(header::ACCEPT_RANGES)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|unit| unit.trim().eq_ignore_ascii_case("bytes"));
+    let length = head
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok()?.parse::<u64>().ok());
+    let (Some(length @ 1..), true, true) = (length, accepts_ranges, head.status().is_success()) else {
+        return download(client, &request).await;
+    };
+    // A changed resource makes the server ignore the ranges and send all of it, which is detected below.
+    if let Some(etag) = head.headers().get(header::ETAG) {
+        request.headers_mut().insert(header::IF_RANGE, etag.clone());
+    }
+    let segment_len = length.div_ceil((segments.clamp(1, MAX_SEGMENTS) as u64).min(length));
+    // Rounding the length up can leave fewer segments than asked for, e.g. 10 bytes in 8 segments take 5 of 2 bytes.
+    let segments = length.div_ceil(segment_len);
+    let parts = (0..segments).map(|i| (i * segment_len, ((i + 1) * segment_len).min(length) - 1));
+    let parts = futures::future::try_join_all(parts.map(|(start, end)| {
+        let request = with_range(request.clone(), start, Some(end));
+        async move {
+            let mut response = client.send(&request).await?;
+            let range = response.content_range();
+            // The length comes from the server, so the buffer grows with the bytes actually received instead of being
+            // allocated up front, and a body longer than the range stops being read one byte past it.
+            let limit = (end - start + 1).saturating_add(1);
+            let mut body = Vec::new();
+            futures::AsyncReadExt::read_to_end(&mut futures::AsyncReadExt::take(response.body_mut(), limit), &mut body).await?;
+            let complete = response.status() == StatusCode::PARTIAL_CONTENT
+                && range.is_some_and(|range| range.start == start && range.end == end)
+                && body.len() as u64 == end - start + 1;
+            Ok::<_, HttpError>(complete.then_some(body))
+        }
+    }))
+    .await?;
+    match parts.into_iter().collect::<Option<Vec<_>>>() {
+        Some(parts) => Ok(parts.concat()),
+        None => download(client, &request).await,
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn download(client: &Client, request: &Request<Vec<u8>>) -> Result<Vec<u8>, HttpError> {
+    let mut request = request.clone();
+    request.headers_mut().remove(header::IF_RANGE);
+    let mut response = client.send(&request).await?;
+    let mut body = Vec::new();
+    futures::AsyncReadExt::read_to_end(response.body_mut(), &mut body).await?;
+    match response.status().is_success() {
+        true => Ok(body),
+        false => Err(HttpError::Status {
+            status: response.status(),
+            body,
+        }),
+    }
+}
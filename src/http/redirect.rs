@@ -0,0 +1,64 @@
+use http::{header, uri::PathAndQuery, HeaderValue, Method, Request, StatusCode, Uri};
+
+/// Decides whether a redirect to the given target may be followed.
+pub type RedirectPolicy = dyn Fn(&Uri) -> bool + Send + Sync;
+
+// The request to send for a redirect response, or `None` if the response isn't a followable redirect.
+pub(crate) fn redirect_request(request: &Request<Vec<u8>>, status: StatusCode, location: Option<&HeaderValue>) -> Option<Request<Vec<u8>>> {
+    let (method, body) = match status {
+        StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND if request.method() == Method::POST => (Method::GET, Vec::new()),
+        StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND | StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT => {
+            (request.method().clone(), request.body().clone())
+        }
+        StatusCode::SEE_OTHER if request.method() == Method::HEAD => (Method::HEAD, Vec::new()),
+        StatusCode::SEE_OTHER => (Method::GET, Vec::new()),
+        _ => return None,
+    };
+    let uri = resolve(request.uri(), location?.to_str().ok()?)?;
+    let mut redirect = Request::new(body);
+    *redirect.method_mut() = method;
+    *redirect.headers_mut() = request.headers().clone();
+    redirect.headers_mut().remove(header::HOST);
+    if redirect.method() != request.method() {
+        redirect.headers_mut().remove(header::CONTENT_LENGTH);
+        redirect.headers_mut().remove(header::CONTENT_TYPE);
+        redirect.headers_mut().remove(header::CONTENT_ENCODING);
+    }
+    if uri.scheme() != request.uri().scheme() || uri.authority() != request.uri().authority() {
+        redirect.headers_mut().remove(header::AUTHORIZATION);
+        redirect.headers_mut().remove(header::COOKIE);
+        redirect.headers_mut().remove(header::PROXY_AUTHORIZATION);
+    }
+    *redirect.uri_mut() = uri;
+    Some(redirect)
+}
+
+pub(crate) fn owned_request(request: &Request<impl AsRef<[u8]>>) -> Request<Vec<u8>> {
+    let mut owned = Request::new(request.body().as_ref().to_vec());
+    *owned.method_mut() = request.method().clone();
+    *owned.uri_mut() = request.uri().clone();
+    *owned.version_mut() = request.version();
+    *owned.headers_mut() = request.headers().clone();
+    owned
+}
+
+// Resolves a `Location` value, which may be relative, against the URI of the request.
+fn resolve(base: &Uri, location: &str) -> Option<Uri> {
+    let location = location.split('#').next().unwrap();
+    let uri = Uri::try_from(location).ok()?;
+    if uri.scheme().is_some() {
+        return Some(uri);
+    }
+    let path_and_query = match location {
+        _ if location.starts_with("//") => return Uri::try_from(format!("{}:{}", base.scheme_str()?, location)).ok(),
+        _ if location.starts_with('/') => location.to_string(),
+        _ if location.starts_with('?') => format!("{}{}", base.path(), location),
+        _ => {
+            let directory = base.path().rsplit_once('/').map_or("", |(directory, _)| directory);
+            format!("{}/{}", directory, location)
+        }
+    };
+    let mut parts = base.clone().into_parts();
+    parts.path_and_query = Some(PathAndQuery::try_from(path_and_query).ok()?);
+    Uri::from_parts(parts).ok()
+}
@@ -1,50 +1,141 @@
 use std::borrow::Cow;
+use std::io;
 
-use std::mem::replace;
-use std::pin::Pin;
+use std::mem::{replace, take};
+use std::net::SocketAddr;
+use std::pin::{pin, Pin};
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
 use async_http_codec::internal::buffer_decode::BufferDecodeState;
-use async_http_codec::internal::buffer_write::BufferWriteState;
-use async_http_codec::internal::io_future::{IoFutureState, IoFutureWithOutputState};
+use async_http_codec::internal::io_future::IoFutureWithOutputState;
 use async_http_codec::{BodyEncodeState, RequestHead, ResponseHead};
+use async_io::Timer;
 
-use futures::{AsyncWrite, Future};
+use futures::{ready, AsyncRead, AsyncWrite, Future, Stream, StreamExt};
 
-use http::uri::{PathAndQuery, Scheme};
-use http::{HeaderMap, HeaderValue, Method, Response, Uri, Version};
-use rustls::ClientConfig;
+use http::uri::PathAndQuery;
+use http::{HeaderMap, HeaderValue, Method, Response, StatusCode, Uri, Version};
 
-use crate::{Transport, TransportError, DEFAULT_CLIENT_CONFIG};
+use crate::{ClientConfig, Transport, TransportError, DEFAULT_CLIENT_CONFIG};
 
-use super::common::extract_origin;
+use super::abort::AbortHandle;
+use super::common::{extract_origin, has_connection_token, host_header};
+use super::connection::ReusePredicate;
+use super::credentials::Credentials;
+#[cfg(feature = "decompression")]
+use super::decompression::{Decompression, ACCEPT_ENCODING};
 use super::error::HttpError;
-use super::response_native::ResponseRead;
+use super::extensions::{PeerAddr, RequestBytes};
+use super::observer::{BodyObserver, Observer};
+use super::response_native::{response_head_decode_state, ResponseRead};
+use super::schemes::SchemeConfig;
+use super::timeouts::{Deadline, TimeoutPhase, Timeouts};
 
-pub(crate) enum RequestSend<'a> {
+const DEFAULT_WRITE_BUFFER_SIZE: usize = 8 * 1024;
+const ERROR_BODY_LIMIT: usize = 64 * 1024;
+const DEFAULT_CONTINUE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Decides from the response head whether its body is read, see [`RequestSend::should_read_body`](super::RequestSend::should_read_body).
+pub(crate) type BodyPredicate = dyn Fn(&Response<()>) -> bool + Send + Sync;
+
+#[derive(Clone)]
+pub(crate) struct RequestOptions {
+    pub(crate) version: Version,
+    pub(crate) observer: Option<Arc<dyn Observer>>,
+    pub(crate) timeouts: Timeouts,
+    pub(crate) close_connection: bool,
+    pub(crate) schemes: SchemeConfig,
+    pub(crate) target: Option<Uri>,
+    pub(crate) tcp_keepalive: Option<Duration>,
+    pub(crate) capture_request_bytes: bool,
+    pub(crate) error_for_status: bool,
+    pub(crate) deadline: Option<Deadline>,
+    pub(crate) omit_content_length: bool,
+    pub(crate) abort: Option<AbortHandle>,
+    pub(crate) reuse_predicate: Option<Arc<ReusePredicate>>,
+    pub(crate) should_read_body: Option<Arc<BodyPredicate>>,
+    pub(crate) strict_headers: bool,
+    pub(crate) connect_to_host_header: bool,
+    pub(crate) credentials: Option<Arc<Credentials>>,
+    #[cfg(feature = "compression")]
+    pub(crate) gzip_body: bool,
+    #[cfg(feature = "raw-headers")]
+    pub(crate) raw_headers: Option<Vec<(String, String)>>,
+}
+
+impl Default for RequestOptions {
+    fn default() -> Self {
+        Self {
+            version: Version::HTTP_11,
+            observer: None,
+            timeouts: Timeouts::default(),
+            close_connection: false,
+            schemes: SchemeConfig::default(),
+            target: None,
+            tcp_keepalive: None,
+            capture_request_bytes: false,
+            error_for_status: false,
+            deadline: None,
+            omit_content_length: false,
+            abort: None,
+            reuse_predicate: None,
+            should_read_body: None,
+            strict_headers: false,
+            connect_to_host_header: false,
+            credentials: None,
+            #[cfg(feature = "compression")]
+            gzip_body: false,
+            #[cfg(feature = "raw-headers")]
+            raw_headers: None,
+        }
+    }
+}
+
+pub(crate) struct RequestSend<'a> {
+    body: Cow<'a, [u8]>,
+    method: Method,
+    uri: Cow<'a, Uri>,
+    headers: Cow<'a, HeaderMap>,
+    options: RequestOptions,
+    started: Option<Instant>,
+    deadline: Option<Timer>,
+    overall_deadline: Option<Timer>,
+    close: bool,
+    request_bytes: Option<Vec<u8>>,
+    head_buffer: Option<&'a mut Vec<u8>>,
+    #[cfg(feature = "decompression")]
+    decompress: bool,
+    state: State,
+}
+
+type PendingConnect = Pin<Box<dyn Future<Output = Result<Transport, TransportError>> + Send>>;
+
+// Every state owns its transport (or the connect future), so dropping a request in flight closes the socket.
+#[allow(clippy::large_enum_variant)]
+enum State {
     Start {
-        body: &'a [u8],
-        method: Method,
-        uri: &'a Uri,
-        headers: &'a HeaderMap,
         client_config: Arc<ClientConfig>,
+        addr: Option<(SocketAddr, String)>,
+    },
+    Authorizing {
+        transport: Transport,
+        authorization: Pin<Box<dyn Future<Output = Result<HeaderValue, HttpError>> + Send>>,
     },
     PendingConnect {
-        body: &'a [u8],
-        method: Method,
-        uri: &'a Uri,
-        headers: &'a HeaderMap,
-        transport: Pin<Box<dyn Future<Output = Result<Transport, TransportError>> + Send>>,
+        transport: PendingConnect,
+    },
+    Connected {
+        transport: Transport,
     },
     SendingHead {
-        body: &'a [u8],
-        write_state: BufferWriteState,
+        head: Vec<u8>,
+        written: usize,
         transport: Transport,
     },
     SendingBody {
-        body: &'a [u8],
-        remaining: &'a [u8],
+        written: usize,
         write_state: BodyEncodeState,
         transport: Transport,
     },
@@ -54,189 +145,1231 @@ pub(crate) enum RequestSend<'a> {
     ReceivingHead {
         transport: Transport,
         dec_state: BufferDecodeState<ResponseHead<'static>>,
+        head_deadline: Option<Timer>,
+        responded: bool,
+    },
+    ReadingErrorBody {
+        status: StatusCode,
+        body: ResponseRead,
+        captured: Vec<u8>,
     },
     Finished,
 }
 
-impl RequestSend<'_> {
+impl<'a> RequestSend<'a> {
     pub fn new(request: &http::Request<impl AsRef<[u8]>>) -> RequestSend<'_> {
         Self::new_with_client_config(request, DEFAULT_CLIENT_CONFIG.clone())
     }
     pub fn new_with_client_config(request: &http::Request<impl AsRef<[u8]>>, client_config: Arc<ClientConfig>) -> RequestSend<'_> {
-        let body = request.body().as_ref();
-        let uri = request.uri();
-        let headers = request.headers();
-        let method = request.method().clone();
-        RequestSend::Start {
-            method,
-            body,
-            uri,
-            headers,
-            client_config,
+        RequestSend {
+            body: Cow::Borrowed(request.body().as_ref()),
+            method: request.method().clone(),
+            uri: Cow::Borrowed(request.uri()),
+            headers: Cow::Borrowed(request.headers()),
+            options: RequestOptions::default(),
+            started: None,
+            deadline: None,
+            overall_deadline: None,
+            close: false,
+            request_bytes: None,
+            head_buffer: None,
+            #[cfg(feature = "decompression")]
+            decompress: false,
+            state: State::Start { client_config, addr: None },
+        }
+    }
+    pub fn new_owned(request: http::Request<impl Into<Vec<u8>>>) -> RequestSend<'static> {
+        let (parts, body) = request.into_parts();
+        RequestSend {
+            body: Cow::Owned(body.into()),
+            method: parts.method,
+            uri: Cow::Owned(parts.uri),
+            headers: Cow::Owned(parts.headers),
+            options: RequestOptions::default(),
+            started: None,
+            deadline: None,
+            overall_deadline: None,
+            close: false,
+            request_bytes: None,
+            head_buffer: None,
+            #[cfg(feature = "decompression")]
+            decompress: false,
+            state: State::Start {
+                client_config: DEFAULT_CLIENT_CONFIG.clone(),
+                addr: None,
+            },
+        }
+    }
+    pub fn on_addr(request: &http::Request<impl AsRef<[u8]>>, addr: SocketAddr, server_name: String) -> RequestSend<'_> {
+        let mut send = Self::new(request);
+        send.state = State::Start {
+            client_config: DEFAULT_CLIENT_CONFIG.clone(),
+            addr: Some((addr, server_name)),
+        };
+        send
+    }
+    pub(crate) fn with_transport(request: &http::Request<impl AsRef<[u8]>>, transport: Transport) -> RequestSend<'_> {
+        RequestSend {
+            body: Cow::Borrowed(request.body().as_ref()),
+            method: request.method().clone(),
+            uri: Cow::Borrowed(request.uri()),
+            headers: Cow::Borrowed(request.headers()),
+            options: RequestOptions::default(),
+            started: None,
+            deadline: None,
+            overall_deadline: None,
+            close: false,
+            request_bytes: None,
+            head_buffer: None,
+            #[cfg(feature = "decompression")]
+            decompress: false,
+            state: State::Connected { transport },
+        }
+    }
+    // The head is encoded into `buffer`, which gets the allocation back once the head is sent.
+    pub(crate) fn set_head_buffer(&mut self, buffer: &'a mut Vec<u8>) {
+        self.head_buffer = Some(buffer);
+    }
+    pub(crate) fn options_mut(&mut self) -> &mut RequestOptions {
+        match self.state {
+            State::Start { .. } | State::Connected { .. } => &mut self.options,
+            _ => panic!("request options changed after polling"),
+        }
+    }
+    fn observe(&self, f: impl FnOnce(&dyn Observer, &Uri, std::time::Duration)) {
+        if let (Some(observer), Some(started)) = (&self.options.observer, self.started) {
+            f(observer.as_ref(), &self.uri, started.elapsed())
+        }
+    }
+    fn set_deadline(&mut self, timeout: Option<Duration>) {
+        self.deadline = timeout.map(Timer::after);
+    }
+    fn poll_deadline(&mut self, cx: &mut Context, phase: TimeoutPhase) -> Result<(), HttpError> {
+        if let Some(timer) = &mut self.deadline {
+            if Pin::new(timer).poll(cx).is_ready() {
+                trace_event!(?phase, "timeout elapsed");
+                return Err(HttpError::Timeout(phase));
+            }
+        }
+        Ok(())
+    }
+    // Builds the response once its head has been received, handing the transport over to the body.
+    fn response_from_head(
+        &mut self,
+        transport: Transport,
+        #[allow(unused_mut)] mut head: ResponseHead<'static>,
+    ) -> Result<Response<ResponseRead>, HttpError> {
+        trace_record!("status", head.status().as_u16());
+        trace_event!("response head received");
+        self.observe(|observer, uri, elapsed| observer.on_response_head(uri, head.status(), elapsed));
+        self.deadline = None;
+        let peer_addr = transport.peer_addr().ok();
+        #[cfg(feature = "tls")]
+        let tls_info = transport.tls_info();
+        let mut body = ResponseRead::new(transport, &self.method, &head)?;
+        if self.close {
+            body.disable_reuse();
+        }
+        if let Some(timeout) = self.options.timeouts.body {
+            body.set_timeout(Timer::after(timeout));
+        }
+        if let Some(deadline) = self.options.deadline {
+            body.set_deadline(Timer::at(deadline.instant()));
+        }
+        if let Some(abort) = &self.options.abort {
+            body.set_abort_handle(abort.clone());
+        }
+        if let (Some(observer), Some(started)) = (&self.options.observer, self.started) {
+            body.set_observer(BodyObserver::new(observer.clone(), self.uri.clone().into_owned(), head.status(), started));
+        }
+        #[cfg(feature = "decompression")]
+        if let Some(decompression) = self
+            .decompress
+            .then(|| head.headers().get(http::header::CONTENT_ENCODING))
+            .flatten()
+            .and_then(Decompression::from_content_encoding)
+        {
+            body.set_decompression(decompression);
+            head.headers_mut().remove(http::header::CONTENT_ENCODING);
+            head.headers_mut().remove(http::header::CONTENT_LENGTH);
+        }
+        let mut parts: http::response::Parts = head.into();
+        if let Some(addr) = peer_addr {
+            parts.extensions.insert(PeerAddr(addr));
+        }
+        #[cfg(feature = "tls")]
+        if let Some(tls_info) = tls_info {
+            parts.extensions.insert(tls_info);
+        }
+        if let Some(request_bytes) = self.request_bytes.take() {
+            trace_event!(request = %String::from_utf8_lossy(&request_bytes), "request bytes");
+            parts.extensions.insert(RequestBytes(request_bytes));
+        }
+        if let Some(predicate) = &self.options.reuse_predicate {
+            let head = Response::from_parts(parts, ());
+            if !predicate(&head) {
+                body.disable_reuse();
+            }
+            parts = head.into_parts().0;
+        }
+        if let Some(should_read_body) = &self.options.should_read_body {
+            let head = Response::from_parts(parts, ());
+            if !should_read_body(&head) {
+                trace_event!("response body discarded");
+                body.discard();
+            }
+            parts = head.into_parts().0;
+        }
+        Ok(Response::from_parts(parts, body))
+    }
+    fn receive_head(&mut self, transport: Transport, dec_state: BufferDecodeState<ResponseHead<'static>>) {
+        self.set_deadline(self.options.timeouts.first_byte);
+        self.state = State::ReceivingHead {
+            dec_state,
+            transport,
+            head_deadline: self.options.timeouts.head.map(Timer::after),
+            responded: false,
         }
     }
     pub fn poll(&mut self, cx: &mut Context) -> Poll<Result<http::Response<ResponseRead>, HttpError>> {
+        if self.options.abort.as_ref().is_some_and(|abort| abort.poll_aborted(cx)) {
+            trace_event!("aborted");
+            self.state = State::Finished;
+            return Poll::Ready(Err(HttpError::Aborted));
+        }
+        let p = self.poll_state(cx);
+        if let (Poll::Pending, Some(deadline)) = (&p, self.options.deadline) {
+            let timer = self.overall_deadline.get_or_insert_with(|| Timer::at(deadline.instant()));
+            if Pin::new(timer).poll(cx).is_ready() {
+                trace_event!("deadline exceeded");
+                self.state = State::Finished;
+                return Poll::Ready(Err(HttpError::DeadlineExceeded));
+            }
+        }
+        p
+    }
+    fn poll_state(&mut self, cx: &mut Context) -> Poll<Result<http::Response<ResponseRead>, HttpError>> {
         loop {
-            let s = replace(self, RequestSend::Finished);
+            let s = replace(&mut self.state, State::Finished);
             match s {
-                RequestSend::Start {
-                    method,
-                    body,
-                    uri,
-                    headers,
-                    client_config,
-                } => {
-                    let (scheme, host, port) = extract_origin(uri, headers)?;
-                    let https = match scheme {
-                        _ if scheme == Some(Scheme::HTTP) => false,
-                        _ if scheme == Some(Scheme::HTTPS) => true,
-                        None => true,
-                        Some(scheme) => return Poll::Ready(Err(HttpError::UnexpectedScheme(scheme))),
+                State::Start { client_config, addr } => {
+                    let (https, host, port) = connect_target(&self.uri, &self.headers, client_config, &self.options)?;
+                    let (host, port) = match &addr {
+                        Some((addr, server_name)) => (server_name.clone(), addr.port()),
+                        None => (host, port),
                     };
-                    let https = https.then_some(client_config);
-                    let port = port.unwrap_or(match https {
-                        Some(_) => 443,
-                        None => 80,
-                    });
-                    *self = RequestSend::PendingConnect {
-                        body,
-                        transport: Box::pin(async move { Transport::connect(https, &host, port).await }),
-                        method,
-                        uri,
-                        headers,
+                    trace_record!("host", host.as_str());
+                    trace_record!("port", port);
+                    trace_event!(tls = https.is_some(), "connecting");
+                    if self.options.observer.is_some() {
+                        self.started = Some(Instant::now());
+                    }
+                    self.observe(|observer, uri, _| observer.on_connect_start(uri));
+                    self.set_deadline(self.options.timeouts.connect);
+                    let keepalive = self.options.tcp_keepalive;
+                    let tls_timeout = self.options.timeouts.tls_handshake;
+                    self.state = State::PendingConnect {
+                        transport: Box::pin(async move {
+                            match addr {
+                                Some((addr, _)) => Transport::connect_addr(https, addr, &host, keepalive, tls_timeout).await,
+                                None => Transport::connect(https, &host, port, keepalive, tls_timeout).await,
+                            }
+                        }),
                     }
                 }
-                RequestSend::PendingConnect {
-                    body,
-                    mut transport,
-                    method,
-                    uri,
-                    headers,
-                } => match transport.as_mut().poll(cx) {
+                State::PendingConnect { mut transport } => match transport.as_mut().poll(cx) {
                     Poll::Ready(Ok(transport)) => {
-                        let (_scheme, host, port) = extract_origin(uri, headers)?;
-                        let uri = uri.path_and_query().cloned().unwrap_or_else(|| PathAndQuery::from_static("/")).into();
-                        let mut head = RequestHead::new(method, Cow::Owned(uri), Version::HTTP_11, Cow::Borrowed(headers));
-                        if head.headers().get(http::header::HOST).is_none() {
-                            let host = match port {
-                                Some(port) => HeaderValue::from_str(&format!("{}:{}", host, port)).unwrap(),
-                                None => HeaderValue::from_str(&host).unwrap(),
+                        trace_event!("connected");
+                        self.observe(|observer, uri, elapsed| observer.on_connect_end(uri, elapsed, Ok(())));
+                        self.state = State::Connected { transport };
+                    }
+                    Poll::Ready(Err(err)) => {
+                        trace_event!(error = %err, "connect failed");
+                        let err = HttpError::from(err);
+                        self.observe(|observer, uri, elapsed| observer.on_connect_end(uri, elapsed, Err(&err)));
+                        return Poll::Ready(Err(err));
+                    }
+                    Poll::Pending => {
+                        self.poll_deadline(cx, TimeoutPhase::Connect)?;
+                        self.state = State::PendingConnect { transport };
+                        return Poll::Pending;
+                    }
+                },
+                State::Connected { transport } => {
+                    // Fetched here rather than before connecting, so that requests on an existing connection get it too.
+                    // An `Authorization` header set by the caller takes precedence over the credentials of the client.
+                    if let Some(credentials) = self.options.credentials.take() {
+                        if !self.headers.contains_key(http::header::AUTHORIZATION) {
+                            self.state = State::Authorizing {
+                                transport,
+                                authorization: Box::pin(async move { credentials.authorization().await }),
                             };
-                            head.headers_mut().insert(http::header::HOST, host);
+                            continue;
                         }
-                        if head.headers().get(http::header::CONTENT_LENGTH).is_none() {
-                            let length = HeaderValue::from_str(&format!("{}", body.len())).unwrap();
-                            head.headers_mut().insert(http::header::CONTENT_LENGTH, length);
+                    }
+                    #[cfg(feature = "raw-headers")]
+                    if let Some(raw_headers) = &self.options.raw_headers {
+                        let head = raw_request_head(&self.method, &self.uri, raw_headers, self.body.len(), &self.options)?;
+                        self.close = has_connection_token(&raw_header_map(raw_headers)?, "close");
+                        if self.options.capture_request_bytes {
+                            self.request_bytes = Some(head.clone());
                         }
-                        let write_state = head.encode_state();
-                        *self = RequestSend::SendingHead {
-                            write_state,
-                            transport,
-                            body,
-                        };
+                        self.set_deadline(self.options.timeouts.write);
+                        self.state = State::SendingHead { head, written: 0, transport };
+                        continue;
+                    }
+                    let mut head = request_head(&self.method, &self.uri, &self.headers, &self.options)?;
+                    self.close = has_connection_token(head.headers(), "close");
+                    #[cfg(feature = "compression")]
+                    if self.options.gzip_body && head.headers().get(http::header::CONTENT_ENCODING).is_none() {
+                        self.body = Cow::Owned(gzip(&self.body).map_err(|err| HttpError::IoError(Arc::new(err)))?);
+                        head.headers_mut()
+                            .insert(http::header::CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+                        head.headers_mut().remove(http::header::CONTENT_LENGTH);
+                    }
+                    #[cfg(feature = "decompression")]
+                    if head.headers().get(http::header::ACCEPT_ENCODING).is_none() {
+                        head.headers_mut()
+                            .insert(http::header::ACCEPT_ENCODING, HeaderValue::from_static(ACCEPT_ENCODING));
+                        self.decompress = true;
+                    }
+                    if !self.options.omit_content_length && head.headers().get(http::header::CONTENT_LENGTH).is_none() {
+                        let length = HeaderValue::from_str(&format!("{}", self.body.len())).unwrap();
+                        head.headers_mut().insert(http::header::CONTENT_LENGTH, length);
+                    }
+                    let mut buffer = self.head_buffer.as_deref_mut().map(take).unwrap_or_default();
+                    buffer.clear();
+                    encode_request_head(&head, &mut buffer).map_err(|err| HttpError::IoError(Arc::new(err)))?;
+                    if self.options.capture_request_bytes {
+                        self.request_bytes = Some(buffer.clone());
+                    }
+                    self.set_deadline(self.options.timeouts.write);
+                    self.state = State::SendingHead {
+                        head: buffer,
+                        written: 0,
+                        transport,
+                    };
+                }
+                State::Authorizing {
+                    transport,
+                    mut authorization,
+                } => match authorization.as_mut().poll(cx) {
+                    Poll::Ready(Ok(value)) => {
+                        self.headers.to_mut().insert(http::header::AUTHORIZATION, value);
+                        self.state = State::Connected { transport };
                     }
-                    Poll::Ready(Err(err)) => return Poll::Ready(Err(HttpError::ConnectError(err))),
+                    Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
                     Poll::Pending => {
-                        *self = RequestSend::PendingConnect {
-                            body,
-                            method,
-                            uri,
-                            headers,
-                            transport,
-                        };
+                        self.state = State::Authorizing { transport, authorization };
                         return Poll::Pending;
                     }
                 },
-                RequestSend::SendingHead {
-                    mut write_state,
+                State::SendingHead {
+                    head,
+                    mut written,
                     mut transport,
-                    body,
-                } => match write_state.poll(cx, &mut transport) {
-                    Poll::Ready(Ok(())) => {
-                        let write_state = BodyEncodeState::new(Some(body.len() as u64));
-                        let remaining = body;
-                        *self = RequestSend::SendingBody {
-                            body,
+                } => match Pin::new(&mut transport).poll_write(cx, &head[written..]) {
+                    Poll::Ready(Ok(0)) => return Poll::Ready(Err(closed_without_response(io::ErrorKind::WriteZero.into(), &transport))),
+                    Poll::Ready(Ok(n)) if written + n < head.len() => {
+                        written += n;
+                        self.state = State::SendingHead { head, written, transport };
+                    }
+                    Poll::Ready(Ok(_)) => {
+                        if let Some(buffer) = self.head_buffer.as_deref_mut() {
+                            *buffer = head;
+                        }
+                        trace_event!("head sent");
+                        self.observe(|observer, uri, elapsed| observer.on_head_sent(uri, elapsed));
+                        let write_state = BodyEncodeState::new(Some(self.body.len() as u64));
+                        self.state = State::SendingBody {
                             write_state,
                             transport,
-                            remaining,
+                            written: 0,
                         }
                     }
-                    Poll::Ready(Err(err)) => return Poll::Ready(Err(HttpError::IoError(Arc::new(err)))),
+                    Poll::Ready(Err(err)) => return Poll::Ready(Err(closed_without_response(err, &transport))),
                     Poll::Pending => {
-                        *self = RequestSend::SendingHead {
-                            write_state,
-                            transport,
-                            body,
-                        };
+                        self.poll_deadline(cx, TimeoutPhase::Write)?;
+                        self.state = State::SendingHead { head, written, transport };
                         return Poll::Pending;
                     }
                 },
-                RequestSend::SendingBody {
+                State::SendingBody {
                     mut write_state,
                     mut transport,
-                    body,
-                    mut remaining,
-                } => match write_state.poll_write(&mut transport, cx, remaining) {
+                    mut written,
+                } => match write_state.poll_write(&mut transport, cx, &self.body[written..]) {
                     Poll::Ready(Ok(n)) => {
-                        remaining = &remaining[n..];
-                        match remaining.len() {
-                            0 => *self = RequestSend::Flushing { transport },
+                        if let Some(request_bytes) = &mut self.request_bytes {
+                            request_bytes.extend_from_slice(&self.body[written..written + n]);
+                        }
+                        written += n;
+                        match self.body.len() - written {
+                            0 => {
+                                trace_event!("body sent");
+                                self.state = State::Flushing { transport }
+                            }
                             _ => {
-                                *self = RequestSend::SendingBody {
+                                self.state = State::SendingBody {
                                     write_state,
                                     transport,
-                                    body,
-                                    remaining,
+                                    written,
                                 }
                             }
                         }
                     }
                     Poll::Ready(Err(err)) => return Poll::Ready(Err(HttpError::IoError(Arc::new(err)))),
                     Poll::Pending => {
-                        *self = RequestSend::SendingBody {
+                        self.poll_deadline(cx, TimeoutPhase::Write)?;
+                        self.state = State::SendingBody {
                             write_state,
                             transport,
-                            body,
-                            remaining,
+                            written,
                         };
                         return Poll::Pending;
                     }
                 },
-                RequestSend::Flushing { mut transport } => match Pin::new(&mut transport).poll_flush(cx) {
-                    Poll::Ready(Ok(())) => {
-                        let dec_state = ResponseHead::decode_state();
-                        *self = RequestSend::ReceivingHead { dec_state, transport }
-                    }
+                State::Flushing { mut transport } => match Pin::new(&mut transport).poll_flush(cx) {
+                    Poll::Ready(Ok(())) => self.receive_head(transport, response_head_decode_state(self.options.strict_headers)),
                     Poll::Ready(Err(err)) => return Poll::Ready(Err(HttpError::IoError(Arc::new(err)))),
                     Poll::Pending => {
-                        *self = RequestSend::Flushing { transport };
+                        self.poll_deadline(cx, TimeoutPhase::Write)?;
+                        self.state = State::Flushing { transport };
                         return Poll::Pending;
                     }
                 },
-                RequestSend::ReceivingHead {
+                State::ReceivingHead {
                     mut dec_state,
                     mut transport,
-                } => match dec_state.poll(cx, &mut transport) {
+                    mut head_deadline,
+                    mut responded,
+                } => {
+                    let mut marker = ReadMarker {
+                        transport: &mut transport,
+                        read: false,
+                    };
+                    let p = dec_state.poll(cx, &mut marker);
+                    if marker.read {
+                        self.deadline = None;
+                        responded = true;
+                    }
+                    match p {
+                        Poll::Ready(Ok(head)) => {
+                            let response = self.response_from_head(transport, head)?;
+                            let status = response.status();
+                            if !self.options.error_for_status || !(status.is_client_error() || status.is_server_error()) {
+                                return Poll::Ready(Ok(response));
+                            }
+                            self.state = State::ReadingErrorBody {
+                                status,
+                                body: response.into_body(),
+                                captured: Vec::new(),
+                            };
+                        }
+                        Poll::Ready(Err(err)) if !responded => return Poll::Ready(Err(closed_without_response(err, &transport))),
+                        Poll::Ready(Err(err)) => return Poll::Ready(Err(HttpError::from(err))),
+                        Poll::Pending => {
+                            self.poll_deadline(cx, TimeoutPhase::FirstByte)?;
+                            if let Some(timer) = &mut head_deadline {
+                                if Pin::new(timer).poll(cx).is_ready() {
+                                    trace_event!("head timeout elapsed");
+                                    return Poll::Ready(Err(HttpError::Timeout(TimeoutPhase::Head)));
+                                }
+                            }
+                            self.state = State::ReceivingHead {
+                                transport,
+                                dec_state,
+                                head_deadline,
+                                responded,
+                            };
+                            return Poll::Pending;
+                        }
+                    }
+                }
+                // The body is read up to the limit and then dropped together with the connection.
+                State::ReadingErrorBody {
+                    status,
+                    mut body,
+                    mut captured,
+                } => {
+                    let len = captured.len();
+                    captured.resize((len + 4096).min(ERROR_BODY_LIMIT), 0);
+                    match Pin::new(&mut body).poll_read(cx, &mut captured[len..]) {
+                        Poll::Ready(Ok(n)) => {
+                            captured.truncate(len + n);
+                            if n == 0 || captured.len() == ERROR_BODY_LIMIT {
+                                return Poll::Ready(Err(HttpError::Status { status, body: captured }));
+                            }
+                            self.state = State::ReadingErrorBody { status, body, captured };
+                        }
+                        Poll::Ready(Err(err)) => return Poll::Ready(Err(err.into())),
+                        Poll::Pending => {
+                            captured.truncate(len);
+                            self.state = State::ReadingErrorBody { status, body, captured };
+                            return Poll::Pending;
+                        }
+                    }
+                }
+                State::Finished => panic!("polled finished future"),
+            }
+        }
+    }
+    pub fn is_terminated(&self) -> bool {
+        matches!(self.state, State::Finished)
+    }
+}
+
+/// Request whose body is streamed through [`AsyncWrite`].
+///
+/// The body is sent with chunked transfer encoding unless the request has a `Content-Length` header, in which case
+/// exactly that many bytes have to be written. Closing the writer finishes the body without closing the connection.
+pub struct RequestWrite {
+    uri: Uri,
+    method: Method,
+    headers: HeaderMap,
+    options: RequestOptions,
+    close: bool,
+    #[cfg(feature = "decompression")]
+    decompress: bool,
+    pending_connect: Option<PendingConnect>,
+    transport: Option<Transport>,
+    output: Vec<u8>,
+    output_written: usize,
+    head_sent: bool,
+    framing: Framing,
+    buffer: Vec<u8>,
+    buffer_size: usize,
+    bytes_written: u64,
+    head_len: usize,
+    expect_continue: ExpectContinue,
+    continue_timeout: Duration,
+    head_state: BufferDecodeState<ResponseHead<'static>>,
+    early_head: Option<ResponseHead<'static>>,
+    error: Option<HttpError>,
+}
+
+enum ExpectContinue {
+    Disabled,
+    Flushing,
+    Waiting(Timer),
+}
+
+enum Framing {
+    Fixed { remaining: u64 },
+    Chunked,
+    Finished,
+}
+
+impl RequestWrite {
+    /// Connects to the host of the request and sends its head. The body of `request` is ignored.
+    pub fn start<T>(request: &http::Request<T>) -> Self {
+        let mut write = Self::new(request, None);
+        match connect_target(request.uri(), request.headers(), DEFAULT_CLIENT_CONFIG.clone(), &write.options) {
+            Ok((https, host, port)) => {
+                write.pending_connect = Some(Box::pin(async move { Transport::connect(https, &host, port, None, None).await }))
+            }
+            Err(err) => write.error = Some(err),
+        }
+        write
+    }
+    /// Starts a `POST` to `uri` whose body is written with chunked transfer encoding.
+    pub fn post<U: TryInto<Uri>>(uri: U) -> Self
+    where
+        U::Error: Into<http::Error>,
+    {
+        Self::start_with_method(Method::POST, uri)
+    }
+    /// Starts a `PUT` to `uri` whose body is written with chunked transfer encoding.
+    pub fn put<U: TryInto<Uri>>(uri: U) -> Self
+    where
+        U::Error: Into<http::Error>,
+    {
+        Self::start_with_method(Method::PUT, uri)
+    }
+    /// Starts a `PATCH` to `uri` whose body is written with chunked transfer encoding.
+    pub fn patch<U: TryInto<Uri>>(uri: U) -> Self
+    where
+        U::Error: Into<http::Error>,
+    {
+        Self::start_with_method(Method::PATCH, uri)
+    }
+    // An invalid URI is reported by the first write, like connect errors.
+    fn start_with_method<U: TryInto<Uri>>(method: Method, uri: U) -> Self
+    where
+        U::Error: Into<http::Error>,
+    {
+        match http::Request::builder().method(method).uri(uri).body(()) {
+            Ok(request) => Self::start(&request),
+            Err(err) => {
+                let mut write = Self::new(&http::Request::new(()), None);
+                write.error = Some(HttpError::InvalidRequest(Arc::new(err)));
+                write
+            }
+        }
+    }
+    /// Connects to the host of the request, sends the items of `stream` as the body and waits for the response head.
+    ///
+    /// If the stream yields an error, the connection is closed without finishing the body, so the server can't mistake
+    /// the partial body for a complete one.
+    pub async fn send_stream<T, B, E>(
+        request: &http::Request<T>,
+        stream: impl Stream<Item = Result<B, E>>,
+    ) -> Result<(http::Response<()>, super::ResponseRead), HttpError>
+    where
+        B: AsRef<[u8]>,
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let mut write = Self::start(request);
+        let mut stream = pin!(stream);
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|err| HttpError::IoError(Arc::new(io::Error::other(err))))?;
+            futures::AsyncWriteExt::write_all(&mut write, chunk.as_ref()).await?;
+        }
+        write.response().await
+    }
+    /// Sends the request over an already connected transport instead of connecting to the host of the request.
+    pub fn on_stream<T>(request: &http::Request<T>, transport: impl Into<Transport>) -> Self {
+        Self::new(request, Some(transport.into()))
+    }
+    fn new<T>(request: &http::Request<T>, transport: Option<Transport>) -> Self {
+        let mut write = RequestWrite {
+            uri: request.uri().clone(),
+            method: request.method().clone(),
+            headers: request.headers().clone(),
+            options: RequestOptions::default(),
+            close: false,
+            #[cfg(feature = "decompression")]
+            decompress: false,
+            pending_connect: None,
+            transport,
+            output: Vec::new(),
+            output_written: 0,
+            head_sent: false,
+            framing: Framing::Finished,
+            buffer: Vec::new(),
+            buffer_size: DEFAULT_WRITE_BUFFER_SIZE,
+            bytes_written: 0,
+            head_len: 0,
+            expect_continue: ExpectContinue::Disabled,
+            continue_timeout: DEFAULT_CONTINUE_TIMEOUT,
+            head_state: response_head_decode_state(false),
+            early_head: None,
+            error: None,
+        };
+        if let Err(err) = write.encode_head() {
+            write.error = Some(err);
+        }
+        write
+    }
+    /// Sets how many body bytes are collected before they are sent, so that small writes don't each end up in their own
+    /// TCP segment (and chunk). Buffered bytes are also sent on flush and close. Defaults to 8 KiB, 0 disables buffering.
+    pub fn with_buffer_size(mut self, size: usize) -> Self {
+        self.buffer_size = size;
+        self
+    }
+    /// Sets how long to wait for `100 Continue` after sending the head of a request with `Expect: 100-continue`, before
+    /// sending the body anyway. Defaults to 1 second, since some servers never send the interim response.
+    ///
+    /// If the server sends a final response instead, e.g. `417 Expectation Failed`, writes fail with
+    /// [`HttpError::EarlyResponse`] and [`response`](RequestWrite::response) returns it.
+    pub fn with_continue_timeout(mut self, timeout: Duration) -> Self {
+        self.continue_timeout = timeout;
+        self
+    }
+    /// Sends `target` as the request target instead of the path and query of the URI, e.g. `*` for `OPTIONS *`.
+    pub fn with_request_target(mut self, target: Uri) -> Self {
+        self.options.target = Some(target);
+        if let Err(err) = self.encode_head() {
+            self.error = Some(err);
+        }
+        self
+    }
+    /// Sends the request as `version`, HTTP/1.1 by default. HTTP/1.0 has no chunked encoding, so the request needs a
+    /// `Content-Length` header, otherwise it fails with [`HttpError::UnsupportedVersion`].
+    pub fn with_version(mut self, version: Version) -> Self {
+        self.options.version = version;
+        if let Err(err) = self.encode_head() {
+            self.error = Some(err);
+        }
+        self
+    }
+    /// Number of body bytes accepted by writes so far, without chunk framing. They may still be buffered, see
+    /// [`with_buffer_size`](RequestWrite::with_buffer_size).
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+    fn encode_head(&mut self) -> Result<(), HttpError> {
+        let mut head = request_head(&self.method, &self.uri, &self.headers, &self.options)?;
+        self.close = has_connection_token(head.headers(), "close");
+        #[cfg(feature = "decompression")]
+        if head.headers().get(http::header::ACCEPT_ENCODING).is_none() {
+            head.headers_mut()
+                .insert(http::header::ACCEPT_ENCODING, HeaderValue::from_static(ACCEPT_ENCODING));
+            self.decompress = true;
+        }
+        self.framing = match head.headers().get(http::header::CONTENT_LENGTH) {
+            Some(length) => Framing::Fixed {
+                remaining: length
+                    .to_str()
+                    .ok()
+                    .and_then(|length| length.parse().ok())
+                    .ok_or_else(|| HttpError::InvalidHeaderValue(length.clone()))?,
+            },
+            None if self.options.version == Version::HTTP_10 => return Err(HttpError::UnsupportedVersion(self.options.version)),
+            None => {
+                head.headers_mut()
+                    .insert(http::header::TRANSFER_ENCODING, HeaderValue::from_static("chunked"));
+                Framing::Chunked
+            }
+        };
+        self.expect_continue = match head
+            .headers()
+            .get_all(http::header::EXPECT)
+            .iter()
+            .any(|v| v.as_bytes().trim_ascii().eq_ignore_ascii_case(b"100-continue"))
+        {
+            true => ExpectContinue::Flushing,
+            false => ExpectContinue::Disabled,
+        };
+        self.output = head.to_vec().map_err(|err| HttpError::IoError(Arc::new(err)))?;
+        self.head_len = self.output.len();
+        Ok(())
+    }
+    /// Finishes a chunked body with trailer fields instead of closing it.
+    ///
+    /// If nothing has been written yet and the request has no `Trailer` header, one listing the trailer names is added to
+    /// the head.
+    pub async fn finish_with_trailers(&mut self, trailers: HeaderMap) -> Result<(), HttpError> {
+        if !matches!(self.framing, Framing::Chunked) {
+            return Err(HttpError::TrailersRequireChunked);
+        }
+        self.move_buffer_to_output();
+        if !self.head_sent && self.output_written == 0 && !trailers.is_empty() && !self.headers.contains_key(http::header::TRAILER) {
+            let names = trailers.keys().map(|name| name.as_str()).collect::<Vec<_>>().join(", ");
+            self.headers.insert(http::header::TRAILER, HeaderValue::from_str(&names).unwrap());
+            self.encode_head()?;
+        }
+        self.output.extend_from_slice(b"0\r\n");
+        for (name, value) in &trailers {
+            self.output.extend_from_slice(name.as_str().as_bytes());
+            self.output.extend_from_slice(b": ");
+            self.output.extend_from_slice(value.as_bytes());
+            self.output.extend_from_slice(b"\r\n");
+        }
+        self.output.extend_from_slice(b"\r\n");
+        self.framing = Framing::Finished;
+        futures::AsyncWriteExt::flush(self).await?;
+        Ok(())
+    }
+    /// Finishes the body if that hasn't happened yet and waits for the response head.
+    ///
+    /// If the server responded while the body was still being written, which makes writes fail with
+    /// [`HttpError::EarlyResponse`], that response is returned right away and the connection isn't reused.
+    pub async fn response(mut self) -> Result<(http::Response<()>, super::ResponseRead), HttpError> {
+        if self.early_head.is_none() {
+            futures::AsyncWriteExt::close(&mut self).await?;
+        }
+        let transport = self.transport.take().unwrap();
+        self.pending_response(transport).response().await
+    }
+    /// Splits the request so that the response can be read while the body is still being written, for protocols that
+    /// stream in both directions.
+    ///
+    /// The head is sent first, and with `Expect: 100-continue` the interim response is awaited as well. Since the
+    /// connection can't be reused before both sides are done, it's closed after the response.
+    pub async fn into_duplex(mut self) -> Result<(DuplexWrite, DuplexResponse), HttpError> {
+        futures::AsyncWriteExt::flush(&mut self).await?;
+        let (read, write) = self.transport.take().unwrap().split();
+        let mut response = self.pending_response(Transport::ReadHalf(read));
+        response.close = true;
+        self.transport = Some(Transport::WriteHalf(write));
+        Ok((DuplexWrite { inner: self }, response))
+    }
+    fn pending_response(&mut self, transport: Transport) -> DuplexResponse {
+        DuplexResponse {
+            method: self.method.clone(),
+            uri: self.uri.clone(),
+            headers: self.headers.clone(),
+            options: self.options.clone(),
+            close: self.close,
+            #[cfg(feature = "decompression")]
+            decompress: self.decompress,
+            transport,
+            head_state: replace(&mut self.head_state, response_head_decode_state(self.options.strict_headers)),
+            early_head: self.early_head.take(),
+        }
+    }
+    // A server that answers before reading the whole body (e.g. with 413) may stop reading, so that our writes never complete.
+    // While writes are blocked the response head is read as well, and interim 1xx responses are skipped.
+    fn poll_early_response(&mut self, cx: &mut Context<'_>) -> Option<StatusCode> {
+        let transport = self.transport.as_mut().unwrap();
+        loop {
+            match self.head_state.poll(cx, transport) {
+                Poll::Ready(Ok(head)) if head.status().is_informational() && head.status() != StatusCode::SWITCHING_PROTOCOLS => {
+                    self.head_state = response_head_decode_state(self.options.strict_headers);
+                }
+                Poll::Ready(Ok(head)) => {
+                    let status = head.status();
+                    self.early_head = Some(head);
+                    return Some(status);
+                }
+                Poll::Ready(Err(_)) | Poll::Pending => return None,
+            }
+        }
+    }
+    // Frames the buffered body bytes and queues them for sending. Nothing is queued for an empty buffer, since a zero-length
+    // chunk would end the body, so that only `poll_close` terminates it, also when nothing was written at all.
+    fn move_buffer_to_output(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        if let Framing::Chunked = self.framing {
+            self.output.extend_from_slice(format!("{:X}\r\n", self.buffer.len()).as_bytes());
+        }
+        self.output.append(&mut self.buffer);
+        if let Framing::Chunked = self.framing {
+            self.output.extend_from_slice(b"\r\n");
+        }
+    }
+    fn fail(&mut self, err: io::Error) -> io::Error {
+        let err = HttpError::from(err);
+        self.error = Some(err.clone());
+        err.into()
+    }
+    // Connects if necessary and writes out everything queued in `output`.
+    fn poll_output(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Some(err) = &self.error {
+            return Poll::Ready(Err(err.clone().into()));
+        }
+        if let Some(connect) = &mut self.pending_connect {
+            let transport = ready!(connect.as_mut().poll(cx));
+            self.pending_connect = None;
+            match transport {
+                Ok(transport) => self.transport = Some(transport),
+                Err(err) => {
+                    let err = HttpError::from(err);
+                    self.error = Some(err.clone());
+                    return Poll::Ready(Err(err.into()));
+                }
+            }
+        }
+        // With `Expect: 100-continue`, only the head is sent before the server agrees to receive the body.
+        if !matches!(self.expect_continue, ExpectContinue::Disabled) && self.output_written < self.output.len() {
+            if !self.head_sent {
+                ready!(self.poll_write_output(cx, self.head_len))?;
+            }
+            ready!(self.poll_continue(cx))?;
+        }
+        ready!(self.poll_write_output(cx, self.output.len()))?;
+        self.output.clear();
+        self.output_written = 0;
+        self.head_sent = true;
+        Poll::Ready(Ok(()))
+    }
+    fn poll_write_output(&mut self, cx: &mut Context<'_>, end: usize) -> Poll<io::Result<()>> {
+        while self.output_written < end {
+            let transport = self.transport.as_mut().unwrap();
+            match Pin::new(&mut *transport).poll_write(cx, &self.output[self.output_written..]) {
+                Poll::Ready(Ok(0)) => return Poll::Ready(Err(self.fail(io::ErrorKind::WriteZero.into()))),
+                Poll::Ready(Ok(n)) => self.output_written += n,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(self.fail(err))),
+                Poll::Pending => {
+                    if let Some(status) = self.poll_early_response(cx) {
+                        let err = HttpError::EarlyResponse(status);
+                        self.error = Some(err.clone());
+                        return Poll::Ready(Err(err.into()));
+                    }
+                    return Poll::Pending;
+                }
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+    // Waits for `100 Continue` until the timeout elapses, skipping other interim responses.
+    fn poll_continue(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            match &mut self.expect_continue {
+                ExpectContinue::Disabled => return Poll::Ready(Ok(())),
+                ExpectContinue::Flushing => {
+                    if let Err(err) = ready!(Pin::new(self.transport.as_mut().unwrap()).poll_flush(cx)) {
+                        return Poll::Ready(Err(self.fail(err)));
+                    }
+                    self.expect_continue = ExpectContinue::Waiting(Timer::after(self.continue_timeout));
+                }
+                ExpectContinue::Waiting(timer) => match self.head_state.poll(cx, self.transport.as_mut().unwrap()) {
+                    Poll::Ready(Ok(head)) if head.status() == StatusCode::CONTINUE => {
+                        trace_event!("100 continue received");
+                        self.head_state = response_head_decode_state(self.options.strict_headers);
+                        self.expect_continue = ExpectContinue::Disabled;
+                    }
+                    Poll::Ready(Ok(head)) if head.status().is_informational() && head.status() != StatusCode::SWITCHING_PROTOCOLS => {
+                        self.head_state = response_head_decode_state(self.options.strict_headers);
+                    }
                     Poll::Ready(Ok(head)) => {
-                        let body = ResponseRead::new(transport, &head)?;
-                        let parts: http::response::Parts = head.into();
-                        return Poll::Ready(Ok(Response::from_parts(parts, body)));
+                        let err = HttpError::EarlyResponse(head.status());
+                        self.early_head = Some(head);
+                        self.error = Some(err.clone());
+                        return Poll::Ready(Err(err.into()));
                     }
-                    Poll::Ready(Err(err)) => return Poll::Ready(Err(HttpError::IoError(Arc::new(err)))),
+                    Poll::Ready(Err(err)) => return Poll::Ready(Err(self.fail(err))),
                     Poll::Pending => {
-                        *self = RequestSend::ReceivingHead { transport, dec_state };
-                        return Poll::Pending;
+                        ready!(Pin::new(timer).poll(cx));
+                        trace_event!("100 continue timeout elapsed, sending body");
+                        self.expect_continue = ExpectContinue::Disabled;
                     }
                 },
-                RequestSend::Finished => panic!("polled finished future"),
             }
         }
     }
-    pub fn is_terminated(&self) -> bool {
-        match self {
-            RequestSend::Finished => true,
-            _ => false,
+}
+
+impl AsyncWrite for RequestWrite {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_output(cx))?;
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
         }
+        let n = match &mut this.framing {
+            Framing::Fixed { remaining: 0 } => {
+                return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidInput, "body longer than Content-Length")))
+            }
+            Framing::Fixed { remaining } => {
+                let n = buf.len().min(usize::try_from(*remaining).unwrap_or(usize::MAX));
+                *remaining -= n as u64;
+                n
+            }
+            Framing::Chunked => buf.len(),
+            Framing::Finished => return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+        };
+        this.buffer.extend_from_slice(&buf[..n]);
+        this.bytes_written += n as u64;
+        if this.buffer.len() >= this.buffer_size {
+            this.move_buffer_to_output();
+        }
+        Poll::Ready(Ok(n))
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.move_buffer_to_output();
+        ready!(this.poll_output(cx))?;
+        match ready!(Pin::new(this.transport.as_mut().unwrap()).poll_flush(cx)) {
+            Ok(()) => Poll::Ready(Ok(())),
+            Err(err) => Poll::Ready(Err(this.fail(err))),
+        }
+    }
+    // The terminating chunk is queued only once: the framing is `Finished` from then on, so polling again after `Pending`
+    // or after the close completed just keeps flushing what is left in `output`. Write errors are kept in `self.error` and
+    // returned again by every later poll.
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.move_buffer_to_output();
+        match self.framing {
+            Framing::Fixed { remaining: 0 } | Framing::Finished => {}
+            Framing::Fixed { .. } => return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidInput, "body shorter than Content-Length"))),
+            Framing::Chunked => self.output.extend_from_slice(b"0\r\n\r\n"),
+        }
+        self.framing = Framing::Finished;
+        self.poll_flush(cx)
     }
 }
+
+/// The body side of a request split with [`RequestWrite::into_duplex`].
+///
+/// Closing the writer finishes the body, like with [`RequestWrite`].
+pub struct DuplexWrite {
+    inner: RequestWrite,
+}
+
+impl DuplexWrite {
+    /// Number of body bytes accepted by writes so far, see [`RequestWrite::bytes_written`].
+    pub fn bytes_written(&self) -> u64 {
+        self.inner.bytes_written()
+    }
+    /// Finishes a chunked body with trailer fields instead of closing it.
+    pub async fn finish_with_trailers(&mut self, trailers: HeaderMap) -> Result<(), HttpError> {
+        self.inner.finish_with_trailers(trailers).await
+    }
+}
+
+impl AsyncWrite for DuplexWrite {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+/// The response side of a request split with [`RequestWrite::into_duplex`].
+pub struct DuplexResponse {
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    options: RequestOptions,
+    close: bool,
+    #[cfg(feature = "decompression")]
+    decompress: bool,
+    transport: Transport,
+    head_state: BufferDecodeState<ResponseHead<'static>>,
+    early_head: Option<ResponseHead<'static>>,
+}
+
+impl DuplexResponse {
+    /// Waits for the response head, which servers may send before the request body is finished.
+    pub async fn response(self) -> Result<(http::Response<()>, super::ResponseRead), HttpError> {
+        let mut send = RequestSend {
+            body: Cow::Borrowed(&[]),
+            method: self.method,
+            uri: Cow::Borrowed(&self.uri),
+            headers: Cow::Borrowed(&self.headers),
+            options: self.options,
+            started: None,
+            deadline: None,
+            overall_deadline: None,
+            close: self.close,
+            request_bytes: None,
+            head_buffer: None,
+            #[cfg(feature = "decompression")]
+            decompress: self.decompress,
+            state: State::Finished,
+        };
+        let response = match self.early_head {
+            Some(head) => {
+                send.close = true;
+                send.response_from_head(self.transport, head)?
+            }
+            None => {
+                send.receive_head(self.transport, self.head_state);
+                futures::future::poll_fn(|cx| send.poll(cx)).await?
+            }
+        };
+        let (parts, inner) = response.into_parts();
+        Ok((http::Response::from_parts(parts, ()), super::ResponseRead { inner }))
+    }
+}
+
+// Records whether any part of the response head has been read.
+struct ReadMarker<'t> {
+    transport: &'t mut Transport,
+    read: bool,
+}
+
+impl futures::AsyncRead for ReadMarker<'_> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let p = Pin::new(&mut *self.transport).poll_read(cx, buf);
+        if let Poll::Ready(Ok(1..)) = p {
+            self.read = true;
+        }
+        p
+    }
+}
+
+// A server that only speaks HTTP/2 typically drops the connection as soon as it sees an HTTP/1.1 head, without answering.
+fn closed_without_response(err: io::Error, transport: &Transport) -> HttpError {
+    match err.kind() {
+        io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe | io::ErrorKind::UnexpectedEof => {
+            HttpError::ProtocolMismatch {
+                alpn: transport.alpn_protocol().map(|alpn| String::from_utf8_lossy(alpn).into_owned()),
+            }
+        }
+        _ => HttpError::IoError(Arc::new(err)),
+    }
+}
+
+// Writes all requests before reading the first response, then reads the responses in order, for `Connection::pipeline`.
+// The drained body of the last response is returned, so that its connection can be released.
+pub(crate) async fn pipeline<B: AsRef<[u8]>>(
+    mut transport: Transport,
+    requests: &[http::Request<B>],
+    options: RequestOptions,
+) -> Result<(Vec<Response<Vec<u8>>>, ResponseRead), HttpError> {
+    let mut output = Vec::new();
+    let mut framing = Vec::with_capacity(requests.len());
+    for request in requests {
+        let mut head = request_head(request.method(), request.uri(), request.headers(), &options)?;
+        let close = has_connection_token(head.headers(), "close");
+        #[cfg(feature = "decompression")]
+        let decompress = head.headers().get(http::header::ACCEPT_ENCODING).is_none();
+        #[cfg(feature = "decompression")]
+        if decompress {
+            head.headers_mut()
+                .insert(http::header::ACCEPT_ENCODING, HeaderValue::from_static(ACCEPT_ENCODING));
+        }
+        let body = request.body().as_ref();
+        if head.headers().get(http::header::CONTENT_LENGTH).is_none() {
+            head.headers_mut().insert(http::header::CONTENT_LENGTH, body.len().into());
+        }
+        encode_request_head(&head, &mut output).map_err(|err| HttpError::IoError(Arc::new(err)))?;
+        output.extend_from_slice(body);
+        #[cfg(feature = "decompression")]
+        framing.push((close, decompress));
+        #[cfg(not(feature = "decompression"))]
+        framing.push((close, false));
+    }
+    let written = futures::AsyncWriteExt::write_all(&mut transport, &output).await;
+    if let Err(err) = written.and(futures::AsyncWriteExt::flush(&mut transport).await) {
+        return Err(closed_without_response(err, &transport));
+    }
+    trace_event!(requests = requests.len(), "pipelined requests sent");
+    let mut responses = Vec::with_capacity(requests.len());
+    let mut transport = Some(transport);
+    for (request, (close, _decompress)) in requests.iter().zip(framing) {
+        let mut send = RequestSend {
+            body: Cow::Borrowed(&[]),
+            method: request.method().clone(),
+            uri: Cow::Borrowed(request.uri()),
+            headers: Cow::Borrowed(request.headers()),
+            options: options.clone(),
+            started: None,
+            deadline: None,
+            overall_deadline: None,
+            close,
+            request_bytes: None,
+            head_buffer: None,
+            #[cfg(feature = "decompression")]
+            decompress: _decompress,
+            state: State::Finished,
+        };
+        // A connection that can't be reused leaves the remaining requests unanswered.
+        send.receive_head(
+            transport.take().ok_or(HttpError::ConnectionClosed)?,
+            response_head_decode_state(send.options.strict_headers),
+        );
+        let (parts, mut body) = futures::future::poll_fn(|cx| send.poll(cx)).await?.into_parts();
+        let mut buf = Vec::new();
+        futures::AsyncReadExt::read_to_end(&mut body, &mut buf).await?;
+        responses.push(Response::from_parts(parts, buf));
+        if responses.len() == requests.len() {
+            return Ok((responses, body));
+        }
+        transport = body.into_reusable()?;
+    }
+    unreachable!("pipeline without requests")
+}
+
+// Encodes the head like `RequestHead::to_vec`, but into an existing buffer.
+fn encode_request_head(head: &RequestHead, buffer: &mut Vec<u8>) -> io::Result<()> {
+    use std::io::Write;
+    write!(buffer, "{} {} {:?}\r\n", head.method(), head.uri(), head.version())?;
+    for (name, value) in head.headers() {
+        let value = value
+            .to_str()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid character in header value"))?;
+        write!(buffer, "{name}: {value}\r\n")?;
+    }
+    buffer.extend_from_slice(b"\r\n");
+    Ok(())
+}
+
+// The head without any body framing, shared by `RequestSend` and `RequestWrite`.
+fn request_head<'h>(method: &Method, uri: &Uri, headers: &'h HeaderMap, options: &RequestOptions) -> Result<RequestHead<'h>, HttpError> {
+    if options.version != Version::HTTP_10 && options.version != Version::HTTP_11 {
+        return Err(HttpError::UnsupportedVersion(options.version));
+    }
+    let (_scheme, host, port) = extract_origin(uri, headers)?;
+    // CONNECT requests name the tunnel target in authority form, e.g. `CONNECT example.com:443 HTTP/1.1`.
+    let target = match (&options.target, method, uri.authority()) {
+        (Some(target), _, _) if target == "*" && method != Method::OPTIONS => return Err(HttpError::InvalidMethod(method.clone())),
+        (Some(target), _, _) => target.clone(),
+        (None, &Method::CONNECT, Some(authority)) => Uri::from(authority.clone()),
+        (None, _, _) => uri.path_and_query().cloned().unwrap_or_else(|| PathAndQuery::from_static("/")).into(),
+    };
+    let mut head = RequestHead::new(method.clone(), Cow::Owned(target), options.version, Cow::Borrowed(headers));
+    if head.headers().get(http::header::HOST).is_none() {
+        let host = match port {
+            Some(port) => HeaderValue::from_str(&format!("{}:{}", host, port)).unwrap(),
+            None => HeaderValue::from_str(&host).unwrap(),
+        };
+        head.headers_mut().insert(http::header::HOST, host);
+    }
+    if options.version == Version::HTTP_10 {
+        head.headers_mut().remove(http::header::TRANSFER_ENCODING);
+        if head.headers().get(http::header::CONNECTION).is_none() {
+            head.headers_mut().insert(http::header::CONNECTION, HeaderValue::from_static("close"));
+        }
+    }
+    // Appended rather than inserted so that other tokens like `Upgrade` are kept.
+    if options.close_connection && !has_connection_token(head.headers(), "close") {
+        head.headers_mut().append(http::header::CONNECTION, HeaderValue::from_static("close"));
+    }
+    Ok(head)
+}
+
+// Validates the raw headers, which also rejects line breaks that would allow injecting further headers.
+#[cfg(feature = "raw-headers")]
+fn raw_header_map(raw_headers: &[(String, String)]) -> Result<HeaderMap, HttpError> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in raw_headers {
+        let name = http::header::HeaderName::from_bytes(name.as_bytes()).map_err(|err| HttpError::InvalidRequest(Arc::new(err.into())))?;
+        let value = HeaderValue::from_str(value).map_err(|err| HttpError::InvalidRequest(Arc::new(err.into())))?;
+        headers.append(name, value);
+    }
+    Ok(headers)
+}
+
+// The raw headers are written as given. Headers the head would get automatically, like `Host`, are only appended if no
+// raw header has the same name.
+#[cfg(feature = "raw-headers")]
+fn raw_request_head(
+    method: &Method,
+    uri: &Uri,
+    raw_headers: &[(String, String)],
+    body_len: usize,
+    options: &RequestOptions,
+) -> Result<Vec<u8>, HttpError> {
+    let headers = raw_header_map(raw_headers)?;
+    let head = request_head(method, uri, &headers, options)?;
+    let mut out = format!("{} {} {:?}\r\n", head.method(), head.uri(), head.version()).into_bytes();
+    for (name, value) in raw_headers {
+        out.extend_from_slice(format!("{name}: {value}\r\n").as_bytes());
+    }
+    for (name, value) in head.headers() {
+        if !headers.contains_key(name) {
+            out.extend_from_slice(name.as_str().as_bytes());
+            out.extend_from_slice(b": ");
+            out.extend_from_slice(value.as_bytes());
+            out.extend_from_slice(b"\r\n");
+        }
+    }
+    let framed = headers.contains_key(http::header::CONTENT_LENGTH) || headers.contains_key(http::header::TRANSFER_ENCODING);
+    if !options.omit_content_length && !framed {
+        out.extend_from_slice(format!("content-length: {body_len}\r\n").as_bytes());
+    }
+    out.extend_from_slice(b"\r\n");
+    Ok(out)
+}
+
+// The URI decides where to connect, unless `connect_to_host_header` is set and the request has a valid `Host` header. The
+// scheme of the URI still decides about TLS in that case.
+pub(crate) fn connect_target(
+    uri: &Uri,
+    headers: &HeaderMap,
+    client_config: Arc<ClientConfig>,
+    options: &RequestOptions,
+) -> Result<(Option<Arc<ClientConfig>>, String, u16), HttpError> {
+    let (scheme, mut host, mut port) = extract_origin(uri, headers)?;
+    if options.connect_to_host_header {
+        if let Some((header_host, header_port)) = host_header(headers)? {
+            (host, port) = (header_host, header_port);
+        }
+    }
+    let (default_port, https) = match scheme {
+        None => (443, true),
+        Some(scheme) => options.schemes.get(scheme.as_str()).ok_or(HttpError::UnexpectedScheme(scheme))?,
+    };
+    if https && cfg!(not(feature = "tls")) {
+        return Err(HttpError::TlsUnsupported);
+    }
+    Ok((https.then_some(client_config), host, port.unwrap_or(default_port)))
+}
+
+#[cfg(feature = "compression")]
+fn gzip(body: &[u8]) -> std::io::Result<Vec<u8>> {
+    use std::io::Write;
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(body)?;
+    encoder.finish()
+}
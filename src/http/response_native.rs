@@ -1,59 +1,369 @@
 use std::{
+    borrow::Cow,
     io,
     pin::Pin,
     task::{Context, Poll},
 };
 
-use async_http_codec::{BodyDecodeState, ResponseHead};
-use futures::AsyncRead;
-use http::HeaderValue;
+use async_http_codec::{internal::buffer_decode::BufferDecodeState, BodyDecodeState, ResponseHead};
+use async_io::Timer;
+use futures::{AsyncBufRead, AsyncRead, Future};
+use http::{header::HeaderName, HeaderMap, HeaderValue, Method, StatusCode, Version};
 
 use crate::Transport;
 
+use super::abort::AbortHandle;
+use super::chunked::ChunkedDecoder;
+use super::common::has_connection_token;
+#[cfg(feature = "decompression")]
+use super::decompression::Decompression;
 use super::error::HttpError;
+use super::keep_alive::KeepAlive;
+use super::observer::BodyObserver;
+use super::timeouts::TimeoutPhase;
 
 pub struct ResponseRead {
-    state: BodyDecodeState,
+    state: BodyState,
     transport: Option<Transport>,
     error: Option<HttpError>,
+    observer: Option<BodyObserver>,
+    timeout: Option<Timer>,
+    deadline: Option<Timer>,
+    abort: Option<AbortHandle>,
+    finished: bool,
+    expected: Option<u64>,
+    received: u64,
+    keep_alive: bool,
+    keep_alive_hint: Option<KeepAlive>,
+    reusable: bool,
+    read_buf: Vec<u8>,
+    read_pos: usize,
+    read_cap: usize,
+    #[cfg(feature = "decompression")]
+    decompression: Option<Decompression>,
+}
+
+enum BodyState {
+    Framed(BodyDecodeState),
+    Chunked(ChunkedDecoder),
+    UntilClose { done: bool },
 }
 
 impl ResponseRead {
-    pub(crate) fn new(transport: Transport, head: &ResponseHead) -> Result<Self, HttpError> {
-        // TODO: Return HeaderValue in upstream error
-        let state =
-            BodyDecodeState::from_headers(head.headers()).map_err(|_err| HttpError::UnsupportedTransferEncoding(HeaderValue::from_static("TODO")))?;
+    // The request method is needed because responses to HEAD carry the headers of a GET response but never a body, and a 2xx
+    // response to CONNECT turns the connection into a tunnel right after the head.
+    pub(crate) fn new(transport: Transport, method: &Method, head: &ResponseHead) -> Result<Self, HttpError> {
+        let headers = head.headers();
+        let framed = headers.contains_key(http::header::CONTENT_LENGTH) || headers.contains_key(http::header::TRANSFER_ENCODING);
+        let status = head.status();
+        let tunnel = method == Method::CONNECT && status.is_success();
+        let no_body =
+            method == Method::HEAD || tunnel || status.is_informational() || status == StatusCode::NO_CONTENT || status == StatusCode::NOT_MODIFIED;
+        let empty = no_body
+            || (!headers.contains_key(http::header::TRANSFER_ENCODING) && headers.get(http::header::CONTENT_LENGTH).is_some_and(|v| v == "0"));
+        let expected = match headers.contains_key(http::header::TRANSFER_ENCODING) || no_body {
+            true => None,
+            false => headers.get(http::header::CONTENT_LENGTH).and_then(|v| v.to_str().ok()?.parse().ok()),
+        };
+        let keep_alive = match head.version() {
+            Version::HTTP_10 => has_connection_token(headers, "keep-alive") && !has_connection_token(headers, "close"),
+            _ => !has_connection_token(headers, "close"),
+        };
+        let reusable = keep_alive && (framed || no_body) && status != StatusCode::SWITCHING_PROTOCOLS && !tunnel;
+        let chunked = headers
+            .get_all(http::header::TRANSFER_ENCODING)
+            .iter()
+            .map(|v| v.as_bytes().trim_ascii().eq_ignore_ascii_case(b"chunked"))
+            .eq([true]);
+        let state = if no_body {
+            BodyState::Framed(BodyDecodeState::new(Some(0)))
+        } else if chunked {
+            BodyState::Chunked(ChunkedDecoder::new())
+        } else if framed {
+            // TODO: Return HeaderValue in upstream error
+            BodyState::Framed(
+                BodyDecodeState::from_headers(headers).map_err(|_err| HttpError::UnsupportedTransferEncoding(HeaderValue::from_static("TODO")))?,
+            )
+        } else {
+            BodyState::UntilClose { done: false }
+        };
         Ok(Self {
             state,
             transport: Some(transport),
             error: None,
+            observer: None,
+            timeout: None,
+            deadline: None,
+            abort: None,
+            finished: empty,
+            expected,
+            received: 0,
+            keep_alive,
+            keep_alive_hint: KeepAlive::from_headers(headers),
+            reusable,
+            read_buf: Vec::new(),
+            read_pos: 0,
+            read_cap: 0,
+            #[cfg(feature = "decompression")]
+            decompression: None,
         })
     }
-    pub(crate) fn into_inner(self) -> Result<(BodyDecodeState, Transport), HttpError> {
-        let ResponseRead { state, transport, error } = self;
+    #[cfg(feature = "decompression")]
+    pub(crate) fn set_decompression(&mut self, decompression: Decompression) {
+        self.decompression = Some(decompression);
+    }
+    pub(crate) fn set_timeout(&mut self, timeout: Timer) {
+        self.timeout = Some(timeout);
+    }
+    pub(crate) fn set_deadline(&mut self, deadline: Timer) {
+        self.deadline = Some(deadline);
+    }
+    pub(crate) fn set_abort_handle(&mut self, abort: AbortHandle) {
+        self.abort = Some(abort);
+    }
+    pub(crate) fn disable_reuse(&mut self) {
+        self.reusable = false;
+    }
+    /// Ends the body without reading it. The transport is closed unless the body was already complete, e.g. empty.
+    pub(crate) fn discard(&mut self) {
+        if !self.finished {
+            self.transport = None;
+            self.reusable = false;
+            self.finished = true;
+        }
+        self.state = BodyState::UntilClose { done: true };
+        self.timeout = None;
+        self.deadline = None;
+        #[cfg(feature = "decompression")]
+        {
+            self.decompression = None;
+        }
+    }
+    pub(crate) fn set_observer(&mut self, observer: BodyObserver) {
+        self.observer = Some(observer);
+    }
+    pub(crate) fn into_inner(self) -> Result<Transport, HttpError> {
+        let ResponseRead { transport, error, .. } = self;
         if let Some(err) = error {
             return Err(err);
         }
-        Ok((state, transport.unwrap()))
+        transport.ok_or(HttpError::ConnectionClosed)
+    }
+    pub(crate) fn is_keep_alive(&self) -> bool {
+        self.keep_alive
+    }
+    pub(crate) fn keep_alive_hint(&self) -> Option<KeepAlive> {
+        self.keep_alive_hint
+    }
+    pub(crate) fn trailers(&self) -> HeaderMap {
+        match &self.state {
+            BodyState::Chunked(decoder) => decoder.trailers().clone(),
+            _ => HeaderMap::new(),
+        }
+    }
+    pub(crate) fn into_reusable(self) -> Result<Option<Transport>, HttpError> {
+        if !self.finished {
+            return Err(HttpError::BodyNotDrained);
+        }
+        if !self.reusable {
+            return self.error.map_or(Ok(None), Err);
+        }
+        self.into_inner().map(Some)
     }
 }
 
-impl AsyncRead for ResponseRead {
-    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+impl ResponseRead {
+    fn poll_read_raw(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
         if let Some(err) = &self.error {
             return Poll::Ready(Err(err.clone().into()));
         }
-        let mut transport = self.transport.take().unwrap();
-        match self.state.poll_read(&mut transport, cx, buf) {
+        if !self.finished && self.abort.as_ref().is_some_and(|abort| abort.poll_aborted(cx)) {
+            self.transport = None;
+            self.error = Some(HttpError::Aborted);
+            return Poll::Ready(Err(HttpError::Aborted.into()));
+        }
+        // Only a discarded body is left without a transport and without an error.
+        let Some(mut transport) = self.transport.take() else {
+            return Poll::Ready(Ok(0));
+        };
+        let p = match &mut self.state {
+            BodyState::Framed(state) => state.poll_read(&mut transport, cx, buf),
+            BodyState::Chunked(decoder) => decoder.poll_read(&mut transport, cx, buf),
+            BodyState::UntilClose { done: true } => Poll::Ready(Ok(0)),
+            BodyState::UntilClose { done } => {
+                let p = Pin::new(&mut transport).poll_read(cx, buf);
+                if let Poll::Ready(Ok(0)) = p {
+                    *done = true;
+                }
+                p
+            }
+        };
+        if let (Poll::Pending, Some(timer)) = (&p, &mut self.timeout) {
+            if Pin::new(timer).poll(cx).is_ready() {
+                self.error = Some(HttpError::Timeout(TimeoutPhase::Body));
+                return Poll::Ready(Err(self.error.clone().unwrap().into()));
+            }
+        }
+        if let (Poll::Pending, Some(timer)) = (&p, &mut self.deadline) {
+            if Pin::new(timer).poll(cx).is_ready() {
+                self.error = Some(HttpError::DeadlineExceeded);
+                return Poll::Ready(Err(self.error.clone().unwrap().into()));
+            }
+        }
+        match p {
             Poll::Ready(Err(err)) => {
                 // TODO: Return HeaderValue in upstream error
-                self.error = Some(HttpError::IoError(err.into()));
+                self.error = Some(match self.expected {
+                    Some(expected) if err.kind() == io::ErrorKind::UnexpectedEof => HttpError::IncompleteBody {
+                        expected,
+                        received: self.received,
+                    },
+                    _ => HttpError::from(err),
+                });
                 Poll::Ready(Err(self.error.clone().unwrap().into()))
             }
             p => {
+                if let Poll::Ready(Ok(n)) = p {
+                    self.received += n as u64;
+                }
+                if let (Poll::Ready(Ok(0)), false) = (&p, buf.is_empty()) {
+                    self.finished = true;
+                    self.timeout = None;
+                    self.deadline = None;
+                    if let Some(observer) = self.observer.take() {
+                        observer.complete();
+                    }
+                }
                 self.transport = Some(transport);
                 p
             }
         }
     }
+    #[cfg(feature = "decompression")]
+    fn poll_read_decompressed(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let mut chunk = [0u8; 4096];
+        loop {
+            let decompression = self.decompression.as_mut().unwrap();
+            let n = decompression.read(buf);
+            if n > 0 || buf.is_empty() || decompression.is_finished() {
+                return Poll::Ready(Ok(n));
+            }
+            let n = futures::ready!(self.poll_read_raw(cx, &mut chunk))?;
+            if let Err(err) = self.decompression.as_mut().unwrap().write(&chunk[..n]) {
+                self.error = Some(HttpError::IoError(err.into()));
+                return Poll::Ready(Err(self.error.clone().unwrap().into()));
+            }
+        }
+    }
+}
+
+impl ResponseRead {
+    fn poll_read_body(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        #[cfg(feature = "decompression")]
+        if self.decompression.is_some() {
+            return self.poll_read_decompressed(cx, buf);
+        }
+        self.poll_read_raw(cx, buf)
+    }
+}
+
+// Reads are served from the buffer filled by `poll_fill_buf` first. The buffer only ever holds decoded body bytes, so
+// framing and decompression work the same with or without it.
+impl AsyncRead for ResponseRead {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.read_pos < this.read_cap {
+            let n = buf.len().min(this.read_cap - this.read_pos);
+            buf[..n].copy_from_slice(&this.read_buf[this.read_pos..this.read_pos + n]);
+            this.read_pos += n;
+            return Poll::Ready(Ok(n));
+        }
+        this.poll_read_body(cx, buf)
+    }
+}
+
+impl AsyncBufRead for ResponseRead {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        if this.read_pos >= this.read_cap {
+            let mut read_buf = std::mem::take(&mut this.read_buf);
+            read_buf.resize(8192, 0);
+            let p = this.poll_read_body(cx, &mut read_buf);
+            this.read_buf = read_buf;
+            this.read_pos = 0;
+            this.read_cap = futures::ready!(p)?;
+        }
+        Poll::Ready(Ok(&this.read_buf[this.read_pos..this.read_cap]))
+    }
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        this.read_pos = (this.read_pos + amt).min(this.read_cap);
+    }
+}
+
+// The decoder never reads more than the rest of a possible `\r\n\r\n` terminator, so it stops exactly at the end of the
+// head and the first body bytes are left on the transport for `ResponseRead`, even if head and body arrive together.
+//
+// With `strict_headers`, a header line with bytes not allowed by the spec fails the response with
+// `HttpError::InvalidHeader`, otherwise the line is dropped.
+pub(crate) fn response_head_decode_state(strict_headers: bool) -> BufferDecodeState<ResponseHead<'static>> {
+    match strict_headers {
+        true => BufferDecodeState::new(8192, 128, &|buffer, max_headers| response_head_parse(buffer, max_headers, true)),
+        false => BufferDecodeState::new(8192, 128, &|buffer, max_headers| response_head_parse(buffer, max_headers, false)),
+    }
+}
+
+// Same as the upstream parser, except that the HTTP version of the response is kept instead of always reporting HTTP/1.1.
+fn response_head_parse(buffer: &[u8], max_headers: usize, strict: bool) -> io::Result<ResponseHead<'static>> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    let mut headers = vec![httparse::EMPTY_HEADER; max_headers];
+    let mut parsed = httparse::Response::new(&mut headers);
+    let mut config = httparse::ParserConfig::default();
+    config.ignore_invalid_headers_in_responses(!strict);
+    match config.parse_response(&mut parsed, buffer) {
+        Ok(status) if status.is_partial() => return Err(invalid("malformed HTTP head")),
+        Ok(_) => {}
+        Err(httparse::Error::HeaderName | httparse::Error::HeaderValue) if strict => {
+            return Err(HttpError::InvalidHeader(invalid_header_name(buffer)).into())
+        }
+        Err(err) => return Err(invalid(&err.to_string())),
+    }
+    let version = match parsed.version {
+        Some(0) => Version::HTTP_10,
+        Some(1) => Version::HTTP_11,
+        _ => return Err(invalid("unsupported HTTP version")),
+    };
+    let status = StatusCode::from_u16(parsed.code.unwrap()).map_err(|_| invalid("invalid status code"))?;
+    let mut map = HeaderMap::with_capacity(parsed.headers.len());
+    let lines = buffer.split(|&b| b == b'\n').filter(|line| !line.trim_ascii().is_empty()).count();
+    if parsed.headers.len() + 1 < lines {
+        log::warn!("ignored {} invalid response header lines", lines - 1 - parsed.headers.len());
+    }
+    for header in parsed.headers.iter() {
+        let (Ok(name), Ok(value)) = (HeaderName::from_bytes(header.name.as_bytes()), HeaderValue::from_bytes(header.value)) else {
+            match strict {
+                true => return Err(HttpError::InvalidHeader(header.name.to_string()).into()),
+                false => continue,
+            }
+        };
+        // Appending keeps repeated headers as separate values, which matters for `Set-Cookie` since cookies can't be comma joined.
+        map.append(name, value);
+    }
+    Ok(ResponseHead::new(status, version, Cow::Owned(map)))
+}
+
+// httparse doesn't report which line it rejected, so the head is scanned again for the first invalid one.
+fn invalid_header_name(buffer: &[u8]) -> String {
+    let valid = |line: &[u8]| match line.iter().position(|&b| b == b':') {
+        Some(colon) => HeaderName::from_bytes(&line[..colon]).is_ok() && HeaderValue::from_bytes(line[colon + 1..].trim_ascii()).is_ok(),
+        None => false,
+    };
+    let line = buffer
+        .split(|&b| b == b'\n')
+        .skip(1)
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .find(|line| !line.is_empty() && !valid(line))
+        .unwrap_or_default();
+    let name = line.split(|&b| b == b':').next().unwrap_or_default();
+    String::from_utf8_lossy(name.trim_ascii()).into_owned()
 }
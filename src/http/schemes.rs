@@ -0,0 +1,29 @@
+/// Maps URI schemes to their default port and whether they use TLS.
+///
+/// `http`, `https`, `ws` and `wss` are always known. Registered schemes take precedence over them.
+#[derive(Clone, Debug, Default)]
+pub struct SchemeConfig {
+    schemes: Vec<(String, u16, bool)>,
+}
+
+impl SchemeConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_scheme(mut self, scheme: &str, default_port: u16, use_tls: bool) -> Self {
+        self.schemes.retain(|(s, _, _)| !s.eq_ignore_ascii_case(scheme));
+        self.schemes.push((scheme.to_ascii_lowercase(), default_port, use_tls));
+        self
+    }
+    /// The default port and whether to use TLS for `scheme`.
+    pub fn get(&self, scheme: &str) -> Option<(u16, bool)> {
+        if let Some((_, port, tls)) = self.schemes.iter().find(|(s, _, _)| s.eq_ignore_ascii_case(scheme)) {
+            return Some((*port, *tls));
+        }
+        match scheme.to_ascii_lowercase().as_str() {
+            "http" | "ws" => Some((80, false)),
+            "https" | "wss" => Some((443, true)),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use std::time::{Duration, Instant};
+
+/// Per-phase time limits for a request. Phases without a limit can take as long as they need.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Timeouts {
+    /// Resolving the host and establishing the TCP and TLS connection.
+    pub connect: Option<Duration>,
+    /// The TLS handshake alone, once the TCP connection is established. Catches servers that accept connections but
+    /// stall the handshake, and fails with [`TransportError::TlsTimeout`](crate::TransportError::TlsTimeout). The
+    /// handshake still counts towards `connect`.
+    pub tls_handshake: Option<Duration>,
+    /// Writing the request head and body.
+    pub write: Option<Duration>,
+    /// Waiting for the first byte of the response once the request has been written.
+    pub first_byte: Option<Duration>,
+    /// Receiving the complete response head once the request has been written. Guards against servers that start a
+    /// response but never finish the header block, and fails with [`HttpError::Timeout`](super::HttpError::Timeout) for [`TimeoutPhase::Head`].
+    pub head: Option<Duration>,
+    /// Reading the response body, measured from the response head.
+    pub body: Option<Duration>,
+}
+
+/// A point in time by which a request has to be complete, including reading its response body.
+///
+/// Unlike [`Timeouts`], a deadline doesn't restart for each request, so attaching one deadline to many concurrent requests
+/// makes them all fail with [`HttpError::DeadlineExceeded`](super::HttpError::DeadlineExceeded) once it passes, e.g. when
+/// they share the time budget of a scatter-gather request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+    pub fn at(instant: Instant) -> Self {
+        Self(instant)
+    }
+    pub fn after(duration: Duration) -> Self {
+        Self(Instant::now() + duration)
+    }
+    pub fn instant(&self) -> Instant {
+        self.0
+    }
+    /// The time left until the deadline, zero once it has passed.
+    pub fn remaining(&self) -> Duration {
+        self.0.saturating_duration_since(Instant::now())
+    }
+    pub fn is_expired(&self) -> bool {
+        self.0 <= Instant::now()
+    }
+}
+
+/// The request phase whose [`Timeouts`] limit elapsed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeoutPhase {
+    Connect,
+    Write,
+    FirstByte,
+    Head,
+    Body,
+}
@@ -0,0 +1,43 @@
+use std::sync::Arc;
+
+use http::{uri::Authority, HeaderMap, HeaderValue, Method, Request, Uri};
+
+use crate::{ClientConfig, Transport, DEFAULT_CLIENT_CONFIG};
+
+use super::error::HttpError;
+use super::request_native::{connect_target, RequestOptions};
+use super::RequestSend;
+
+/// Connects to `proxy` and asks it to open a tunnel to `target` with a `CONNECT` request.
+///
+/// The returned transport is connected to the proxy, using TLS if `proxy` is an `https` URI, and is positioned right after
+/// the head of the proxy's 2xx response. Everything written to it from then on is forwarded to `target` as is, so e.g. a
+/// TLS handshake with `target` has to be done on top of it by the caller. A response other than 2xx fails with
+/// [`HttpError::TunnelRejected`].
+///
+/// The host of `target` is sent to the proxy as is and resolved there, so names that only the proxy can resolve work and
+/// no DNS lookup for them happens locally.
+pub async fn connect_tunnel(proxy: &Uri, target: &Authority) -> Result<Transport, HttpError> {
+    connect_tunnel_with(proxy, target, DEFAULT_CLIENT_CONFIG.clone()).await
+}
+
+#[cfg(feature = "tls")]
+pub async fn connect_tunnel_with_client_config(proxy: &Uri, target: &Authority, client_config: Arc<ClientConfig>) -> Result<Transport, HttpError> {
+    connect_tunnel_with(proxy, target, client_config).await
+}
+
+async fn connect_tunnel_with(proxy: &Uri, target: &Authority, client_config: Arc<ClientConfig>) -> Result<Transport, HttpError> {
+    let (tls, host, port) = connect_target(proxy, &HeaderMap::new(), client_config, &RequestOptions::default())?;
+    let transport = Transport::connect(tls, &host, port, None, None).await.map_err(HttpError::from)?;
+    let mut request = Request::new(Vec::<u8>::new());
+    *request.method_mut() = Method::CONNECT;
+    *request.uri_mut() = Uri::from(target.clone());
+    request
+        .headers_mut()
+        .insert(http::header::HOST, HeaderValue::from_str(target.as_str()).unwrap());
+    let response = RequestSend::with_transport(&request, transport).await?;
+    if !response.status().is_success() {
+        return Err(HttpError::TunnelRejected(response.status()));
+    }
+    response.into_body().into_inner()
+}
@@ -0,0 +1,58 @@
+use std::sync::Arc;
+
+use http::Uri;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use super::error::HttpError;
+
+// Everything except the RFC 3986 unreserved characters, so reserved characters never change the meaning of a component.
+pub(crate) const COMPONENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+/// Builds a [`Uri`] from a base and percent-encoded path segments and query parameters.
+///
+/// `UrlBuilder::new("https://api.example.com").path_segment("a b").query("q", "x&y")` builds
+/// `https://api.example.com/a%20b?q=x%26y`.
+#[derive(Clone, Debug)]
+pub struct UrlBuilder {
+    base: String,
+    query: String,
+}
+
+impl UrlBuilder {
+    /// The base is used verbatim and may already contain a path and query.
+    pub fn new(base: impl Into<String>) -> Self {
+        let mut base = base.into();
+        let query = match base.split_once('?') {
+            Some((_, query)) => query.to_string(),
+            None => String::new(),
+        };
+        base.truncate(base.find('?').unwrap_or(base.len()));
+        Self { base, query }
+    }
+    /// Appends a single path segment, encoding `/` and any other reserved characters.
+    pub fn path_segment(mut self, segment: impl AsRef<str>) -> Self {
+        if !self.base.ends_with('/') {
+            self.base.push('/');
+        }
+        self.base.extend(utf8_percent_encode(segment.as_ref(), COMPONENT));
+        self
+    }
+    /// Appends a `key=value` query parameter.
+    pub fn query(mut self, key: impl AsRef<str>, value: impl AsRef<str>) -> Self {
+        if !self.query.is_empty() {
+            self.query.push('&');
+        }
+        self.query.extend(utf8_percent_encode(key.as_ref(), COMPONENT));
+        self.query.push('=');
+        self.query.extend(utf8_percent_encode(value.as_ref(), COMPONENT));
+        self
+    }
+    pub fn build(&self) -> Result<Uri, HttpError> {
+        let mut uri = self.base.clone();
+        if !self.query.is_empty() {
+            uri.push('?');
+            uri.push_str(&self.query);
+        }
+        Uri::try_from(uri).map_err(|err| HttpError::InvalidUri(Arc::new(err)))
+    }
+}
@@ -1,52 +1,274 @@
+macro_rules! trace_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+    };
+}
+
+macro_rules! trace_record {
+    ($field:literal, $value:expr) => {
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record($field, $value);
+    };
+}
+
 mod http;
-// mod ws;
+#[cfg(feature = "test-util")]
+mod mock;
+#[cfg(feature = "test-util")]
+mod test_server;
+mod ws;
 
 use std::{
     io,
-    net::IpAddr,
+    net::{IpAddr, SocketAddr},
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
+    time::Duration,
 };
 
 pub use crate::http::*;
+#[cfg(feature = "tls")]
+use async_io::Timer;
 use async_net::TcpStream;
+#[cfg(feature = "tls")]
+use futures::future::{select, Either};
 use futures::{AsyncRead, AsyncWrite};
+#[cfg(feature = "tls")]
 use futures_rustls::{
     client::TlsStream,
     rustls::{ClientConfig, RootCertStore},
     TlsConnector,
 };
-use rustls_pki_types::{InvalidDnsNameError, ServerName, TrustAnchor};
-// pub use ws::*;
+#[cfg(feature = "test-util")]
+pub use mock::{MockTransport, MockWritten};
+#[cfg(feature = "tls")]
+use rustls_pki_types::TrustAnchor;
+use rustls_pki_types::{InvalidDnsNameError, ServerName};
+#[cfg(feature = "test-util")]
+pub use test_server::TestServer;
+pub use ws::*;
 
+#[allow(clippy::large_enum_variant)]
 pub enum Transport {
     Tcp(TcpStream),
+    #[cfg(feature = "tls")]
     Tls(TlsStream<TcpStream>),
+    #[cfg(feature = "test-util")]
+    Mock(MockTransport),
+    /// The reading side of a connection split with [`Transport::split`], e.g. for a full duplex request, see
+    /// [`RequestWrite::into_duplex`].
+    ReadHalf(futures::io::ReadHalf<Transport>),
+    /// The writing side of a connection split with [`Transport::split`].
+    WriteHalf(futures::io::WriteHalf<Transport>),
 }
 
 impl Transport {
-    async fn connect(tls: Option<Arc<ClientConfig>>, host: &str, port: u16) -> Result<Self, TransportError> {
-        let server = ServerName::try_from(host)
-            .map_err(|err| TransportError::InvalidDnsName(Arc::new(err)))?
-            .to_owned();
+    async fn connect(
+        tls: Option<Arc<ClientConfig>>,
+        host: &str,
+        port: u16,
+        keepalive: Option<Duration>,
+        tls_timeout: Option<Duration>,
+    ) -> Result<Self, TransportError> {
+        let server = server_name(host)?;
         let tcp = match &server {
-            ServerName::DnsName(name) => TcpStream::connect((name.as_ref(), port)).await,
+            ServerName::DnsName(name) => {
+                let addrs = match async_net::resolve((name.as_ref(), port)).await {
+                    Ok(addrs) if addrs.is_empty() => Err(io::Error::new(io::ErrorKind::NotFound, "host has no addresses")),
+                    result => result,
+                }
+                .map_err(|error| TransportError::Dns {
+                    host: name.as_ref().to_string(),
+                    error: Arc::new(error),
+                })?;
+                TcpStream::connect(addrs.as_slice()).await
+            }
             ServerName::IpAddress(ip) => TcpStream::connect((IpAddr::from(*ip), port)).await,
             _ => unreachable!(),
         }
+        .and_then(|tcp| set_keepalive(tcp, keepalive))
         .map_err(|err| TransportError::TcpConnect(Arc::new(err)))?;
+        Self::handshake(tls, server, tcp, tls_timeout).await
+    }
+    // Connects to an already resolved address, using `server_name` for SNI and certificate validation.
+    async fn connect_addr(
+        tls: Option<Arc<ClientConfig>>,
+        addr: SocketAddr,
+        server_name: &str,
+        keepalive: Option<Duration>,
+        tls_timeout: Option<Duration>,
+    ) -> Result<Self, TransportError> {
+        let server = self::server_name(server_name)?;
+        let tcp = TcpStream::connect(addr)
+            .await
+            .and_then(|tcp| set_keepalive(tcp, keepalive))
+            .map_err(|err| TransportError::TcpConnect(Arc::new(err)))?;
+        Self::handshake(tls, server, tcp, tls_timeout).await
+    }
+    #[cfg_attr(not(feature = "tls"), allow(unused_variables))]
+    async fn handshake(
+        tls: Option<Arc<ClientConfig>>,
+        server: ServerName<'static>,
+        tcp: TcpStream,
+        timeout: Option<Duration>,
+    ) -> Result<Self, TransportError> {
         let transport = match tls {
             None => Transport::Tcp(tcp),
+            #[cfg(not(feature = "tls"))]
+            Some(_) => unreachable!("https URIs are rejected by connect_target without the tls feature"),
+            #[cfg(feature = "tls")]
             Some(client_config) => {
-                let tls = TlsConnector::from(client_config)
-                    .connect(server, tcp)
-                    .await
-                    .map_err(|err| TransportError::TlsConnect(Arc::new(err)))?;
+                let server_name = server.to_str().into_owned();
+                let connect = TlsConnector::from(client_config).connect(server, tcp);
+                let result = match timeout {
+                    None => connect.await,
+                    Some(timeout) => match select(connect, Timer::after(timeout)).await {
+                        Either::Left((result, _)) => result,
+                        Either::Right(_) => return Err(TransportError::TlsTimeout { server_name }),
+                    },
+                };
+                let tls = result.map_err(|err| match err.get_ref().and_then(|inner| inner.downcast_ref::<rustls::Error>()) {
+                    Some(rustls::Error::InvalidCertificate(rustls::CertificateError::Expired)) => TransportError::CertificateExpired { server_name },
+                    Some(error) => TransportError::TlsHandshake {
+                        server_name,
+                        error: error.clone(),
+                    },
+                    None => TransportError::TlsConnect(Arc::new(err)),
+                })?;
                 Transport::Tls(tls)
             }
         };
-        Ok(transport)
+        // Only HTTP/1 is spoken over these connections, so an HTTP/1 head sent on e.g. an `h2` connection would just get
+        // garbage back.
+        match transport.alpn_protocol() {
+            None | Some(b"http/1.1" | b"http/1.0") => Ok(transport),
+            Some(alpn) => Err(TransportError::UnexpectedAlpn(String::from_utf8_lossy(alpn).into_owned())),
+        }
+    }
+    /// The negotiated protocol version and cipher suite, if this is a TLS connection.
+    #[cfg(feature = "tls")]
+    pub fn tls_info(&self) -> Option<TlsInfo> {
+        match self {
+            Transport::Tcp(_) => None,
+            #[cfg(feature = "test-util")]
+            Transport::Mock(_) => None,
+            Transport::ReadHalf(_) | Transport::WriteHalf(_) => None,
+            Transport::Tls(tls) => {
+                let connection = tls.get_ref().1;
+                Some(TlsInfo {
+                    protocol_version: connection.protocol_version()?,
+                    cipher_suite: connection.negotiated_cipher_suite()?.suite(),
+                })
+            }
+        }
+    }
+    /// The protocol selected through ALPN, if this is a TLS connection and the server picked one.
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        match self {
+            Transport::Tcp(_) => None,
+            #[cfg(feature = "tls")]
+            Transport::Tls(tls) => tls.get_ref().1.alpn_protocol(),
+            #[cfg(feature = "test-util")]
+            Transport::Mock(_) => None,
+            Transport::ReadHalf(_) | Transport::WriteHalf(_) => None,
+        }
+    }
+    /// Splits the connection into halves that can be read from and written to concurrently, e.g. from different tasks.
+    ///
+    /// Both halves share the connection behind a lock, which for TLS also guards the session state that reads and writes
+    /// both update. A poll on one half only waits while the other half is being polled, not until its I/O completes. The
+    /// halves can be put back together with [`ReadHalf::reunite`](futures::io::ReadHalf::reunite).
+    pub fn split(self) -> (futures::io::ReadHalf<Transport>, futures::io::WriteHalf<Transport>) {
+        futures::AsyncReadExt::split(self)
+    }
+    /// Whether the connection was established over IPv4 or IPv6, `None` if it isn't a socket or is already closed.
+    pub fn address_family(&self) -> Option<AddressFamily> {
+        self.peer_addr().ok().map(AddressFamily::from)
+    }
+    pub(crate) fn peer_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            Transport::Tcp(tcp) => tcp.peer_addr(),
+            #[cfg(feature = "tls")]
+            Transport::Tls(tls) => tls.get_ref().0.peer_addr(),
+            #[cfg(feature = "test-util")]
+            Transport::Mock(_) => Err(io::ErrorKind::NotConnected.into()),
+            Transport::ReadHalf(_) | Transport::WriteHalf(_) => Err(io::ErrorKind::NotConnected.into()),
+        }
+    }
+    // An idle connection must not be readable: end of stream or an error mean the server closed or reset it, and bytes
+    // the server sent without being asked can't belong to the next response either. Mock transports are never stale, and
+    // reading from them would consume the canned response.
+    pub(crate) fn is_stale(&mut self) -> bool {
+        #[cfg(feature = "test-util")]
+        if let Transport::Mock(_) = self {
+            return false;
+        }
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        Pin::new(self).poll_read(&mut cx, &mut [0; 1]).is_ready()
+    }
+}
+
+/// Parameters of an established TLS connection.
+#[cfg(feature = "tls")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TlsInfo {
+    pub protocol_version: rustls::ProtocolVersion,
+    pub cipher_suite: rustls::CipherSuite,
+}
+
+/// The address family of the socket a connection was established on.
+///
+/// This follows the peer address as reported by the socket, so an IPv4-mapped IPv6 address counts as IPv6.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AddressFamily {
+    Ipv4,
+    Ipv6,
+}
+
+impl From<SocketAddr> for AddressFamily {
+    fn from(addr: SocketAddr) -> Self {
+        match addr {
+            SocketAddr::V4(_) => AddressFamily::Ipv4,
+            SocketAddr::V6(_) => AddressFamily::Ipv6,
+        }
+    }
+}
+
+// Only the idle time before the first probe is set. The interval between probes and the number of probes before the
+// connection is dropped stay at the system defaults, since not every platform allows changing them per socket.
+fn set_keepalive(tcp: TcpStream, keepalive: Option<Duration>) -> io::Result<TcpStream> {
+    if let Some(time) = keepalive {
+        socket2::SockRef::from(&tcp).set_tcp_keepalive(&socket2::TcpKeepalive::new().with_time(time))?;
+    }
+    Ok(tcp)
+}
+
+// URI hosts keep the brackets around IPv6 literals (`[::1]`), which have to go before the host can be parsed as an address.
+fn server_name(host: &str) -> Result<ServerName<'static>, TransportError> {
+    let host = host.strip_prefix('[').and_then(|host| host.strip_suffix(']')).unwrap_or(host);
+    let server = ServerName::try_from(host).map_err(|err| TransportError::InvalidDnsName(Arc::new(err)))?;
+    Ok(server.to_owned())
+}
+
+impl From<TcpStream> for Transport {
+    fn from(tcp: TcpStream) -> Self {
+        Transport::Tcp(tcp)
+    }
+}
+
+#[cfg(feature = "tls")]
+impl From<TlsStream<TcpStream>> for Transport {
+    fn from(tls: TlsStream<TcpStream>) -> Self {
+        Transport::Tls(tls)
+    }
+}
+
+#[cfg(feature = "test-util")]
+impl From<MockTransport> for Transport {
+    fn from(mock: MockTransport) -> Self {
+        Transport::Mock(mock)
     }
 }
 
@@ -56,7 +278,12 @@ impl AsyncRead for Transport {
     fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
         match self.get_mut() {
             Transport::Tcp(tcp) => Pin::new(tcp).poll_read(cx, buf),
+            #[cfg(feature = "tls")]
             Transport::Tls(tls) => Pin::new(tls).poll_read(cx, buf),
+            #[cfg(feature = "test-util")]
+            Transport::Mock(mock) => Pin::new(mock).poll_read(cx, buf),
+            Transport::ReadHalf(read) => Pin::new(read).poll_read(cx, buf),
+            Transport::WriteHalf(_) => Poll::Ready(Err(io::ErrorKind::Unsupported.into())),
         }
     }
 }
@@ -65,21 +292,36 @@ impl AsyncWrite for Transport {
     fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
         match self.get_mut() {
             Transport::Tcp(tcp) => Pin::new(tcp).poll_write(cx, buf),
+            #[cfg(feature = "tls")]
             Transport::Tls(tls) => Pin::new(tls).poll_write(cx, buf),
+            #[cfg(feature = "test-util")]
+            Transport::Mock(mock) => Pin::new(mock).poll_write(cx, buf),
+            Transport::WriteHalf(write) => Pin::new(write).poll_write(cx, buf),
+            Transport::ReadHalf(_) => Poll::Ready(Err(io::ErrorKind::Unsupported.into())),
         }
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         match self.get_mut() {
             Transport::Tcp(tcp) => Pin::new(tcp).poll_flush(cx),
+            #[cfg(feature = "tls")]
             Transport::Tls(tls) => Pin::new(tls).poll_flush(cx),
+            #[cfg(feature = "test-util")]
+            Transport::Mock(mock) => Pin::new(mock).poll_flush(cx),
+            Transport::WriteHalf(write) => Pin::new(write).poll_flush(cx),
+            Transport::ReadHalf(_) => Poll::Ready(Err(io::ErrorKind::Unsupported.into())),
         }
     }
 
     fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         match self.get_mut() {
             Transport::Tcp(tcp) => Pin::new(tcp).poll_close(cx),
+            #[cfg(feature = "tls")]
             Transport::Tls(tls) => Pin::new(tls).poll_close(cx),
+            #[cfg(feature = "test-util")]
+            Transport::Mock(mock) => Pin::new(mock).poll_close(cx),
+            Transport::WriteHalf(write) => Pin::new(write).poll_close(cx),
+            Transport::ReadHalf(_) => Poll::Ready(Err(io::ErrorKind::Unsupported.into())),
         }
     }
 }
@@ -89,23 +331,97 @@ use thiserror::Error;
 #[derive(Error, Debug, Clone)]
 pub enum TransportError {
     #[error("invalid host name: {0:?}")]
-    InvalidDnsName(Arc<InvalidDnsNameError>),
+    InvalidDnsName(#[source] Arc<InvalidDnsNameError>),
+    #[error("failed to resolve {host}: {error}")]
+    Dns {
+        host: String,
+        #[source]
+        error: Arc<io::Error>,
+    },
     #[error("tcp connect error: {0:?}")]
-    TcpConnect(Arc<io::Error>),
+    TcpConnect(#[source] Arc<io::Error>),
     #[error("tls connect error: {0:?}")]
-    TlsConnect(Arc<io::Error>),
+    TlsConnect(#[source] Arc<io::Error>),
+    #[cfg(feature = "tls")]
+    #[error("tls handshake with {server_name} failed: {error}")]
+    TlsHandshake {
+        server_name: String,
+        #[source]
+        error: rustls::Error,
+    },
+    /// The server's certificate is past its `notAfter` time. rustls doesn't report the date itself.
+    #[error("certificate of {server_name} has expired")]
+    CertificateExpired { server_name: String },
+    #[error("tls handshake with {server_name} timed out")]
+    TlsTimeout { server_name: String },
+    #[error("server selected unsupported protocol {0:?} through ALPN")]
+    UnexpectedAlpn(String),
+}
+
+/// A client config trusting the Mozilla root certificates bundled through `webpki-roots`.
+#[cfg(feature = "tls")]
+pub fn default_config_webpki() -> Arc<ClientConfig> {
+    client_config_with_roots(webpki_root_store())
+}
+
+/// A client config trusting exactly the certificates in `roots`.
+///
+/// To trust internal CAs in addition to the public ones, start from [`webpki_root_store`]. Certificates in PEM files can
+/// be read with the `rustls-pemfile` crate, whose `certs` function yields the DER certificates to pass to
+/// `RootCertStore::add_parsable_certificates`.
+#[cfg(feature = "tls")]
+pub fn client_config_with_roots(roots: RootCertStore) -> Arc<ClientConfig> {
+    Arc::new(ClientConfig::builder().with_root_certificates(roots).with_no_client_auth())
+}
+
+/// Like [`default_config_webpki`], but handshakes with servers that don't support TLS 1.3 fail.
+#[cfg(feature = "tls")]
+pub fn default_config_tls13_only() -> Arc<ClientConfig> {
+    Arc::new(
+        ClientConfig::builder_with_protocol_versions(&[&rustls::version::TLS13])
+            .with_root_certificates(webpki_root_store())
+            .with_no_client_auth(),
+    )
+}
+
+/// A store with the Mozilla root certificates bundled through `webpki-roots`.
+#[cfg(feature = "tls")]
+pub fn webpki_root_store() -> RootCertStore {
+    let roots = webpki_roots::TLS_SERVER_ROOTS.iter().map(|t| TrustAnchor {
+        subject: t.subject.into(),
+        subject_public_key_info: t.spki.into(),
+        name_constraints: t.name_constraints.map(Into::into),
+    });
+    let mut root_store = RootCertStore::empty();
+    root_store.extend(roots);
+    root_store
+}
+
+/// A client config trusting the certificates of the operating system's trust store.
+///
+/// Certificates in the store that can't be parsed are skipped. Fails if the store can't be loaded at all.
+#[cfg(feature = "native-roots")]
+pub fn default_config_native() -> io::Result<Arc<ClientConfig>> {
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(rustls_native_certs::load_native_certs()?);
+    Ok(client_config_with_roots(root_store))
 }
 
 lazy_static::lazy_static! {
     pub (crate) static ref DEFAULT_CLIENT_CONFIG: Arc<ClientConfig> = {
-        let roots = webpki_roots::TLS_SERVER_ROOTS
-        .iter()
-        .map(|t| {TrustAnchor{subject: t.subject.into(), subject_public_key_info: t.spki.into() , name_constraints: t.name_constraints.map(Into::into)}});
-        let mut root_store = RootCertStore::empty();
-        root_store.extend(roots);
-        let config = ClientConfig::builder()
-            .with_root_certificates(root_store)
-            .with_no_client_auth();
-        Arc::new(config)
+        #[cfg(feature = "native-roots")]
+        match default_config_native() {
+            Ok(config) => return config,
+            Err(err) => log::warn!("failed to load native root certificates, falling back to webpki-roots: {err}"),
+        }
+        #[cfg(feature = "tls")]
+        return default_config_webpki();
+        #[cfg(not(feature = "tls"))]
+        Arc::new(ClientConfig)
     };
 }
+
+// Stands in for the rustls config without the `tls` feature, so that connections are set up the same way either way.
+// `https` URIs are rejected before it would be used.
+#[cfg(not(feature = "tls"))]
+pub(crate) struct ClientConfig;
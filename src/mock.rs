@@ -0,0 +1,65 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use futures::{AsyncRead, AsyncWrite};
+
+/// In-memory [`Transport`](crate::Transport) for tests, which serves canned response bytes and records everything written.
+///
+/// Reads return the canned bytes and then end of file. Use it with e.g. [`Connection::from_transport`](crate::Connection::from_transport)
+/// or [`RequestWrite::on_stream`](crate::RequestWrite::on_stream).
+pub struct MockTransport {
+    response: Vec<u8>,
+    read: usize,
+    written: MockWritten,
+}
+
+impl MockTransport {
+    pub fn new(response: impl Into<Vec<u8>>) -> Self {
+        Self {
+            response: response.into(),
+            read: 0,
+            written: MockWritten::default(),
+        }
+    }
+    /// A handle to the bytes written to this transport, which stays usable after the transport has been moved into a request.
+    pub fn written(&self) -> MockWritten {
+        self.written.clone()
+    }
+}
+
+/// The bytes written to a [`MockTransport`] so far.
+#[derive(Clone, Default)]
+pub struct MockWritten(Arc<Mutex<Vec<u8>>>);
+
+impl MockWritten {
+    pub fn bytes(&self) -> Vec<u8> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl AsyncRead for MockTransport {
+    fn poll_read(self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let n = buf.len().min(this.response.len() - this.read);
+        buf[..n].copy_from_slice(&this.response[this.read..this.read + n]);
+        this.read += n;
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl AsyncWrite for MockTransport {
+    fn poll_write(self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.written.0.lock().unwrap().extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
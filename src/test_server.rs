@@ -0,0 +1,228 @@
+use std::{
+    collections::VecDeque,
+    io,
+    net::SocketAddr,
+    pin::pin,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use async_http_codec::{BodyDecodeState, RequestHead};
+use futures::{
+    channel::oneshot,
+    future::{select, Either},
+    AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt,
+};
+#[cfg(feature = "tls")]
+use futures_rustls::{rustls::ClientConfig, TlsAcceptor};
+use http::{header, Method, Request, Response, Uri};
+
+/// A local HTTP/1.1 server for tests that need real round trips over TCP or TLS, where a [`MockTransport`](crate::MockTransport)
+/// isn't enough.
+///
+/// The server runs on its own threads, so it works with any executor. It keeps connections alive unless the request asks
+/// to close them, and stops accepting connections when dropped.
+pub struct TestServer {
+    addr: SocketAddr,
+    shared: Arc<Shared>,
+    #[cfg(feature = "tls")]
+    client_config: Option<Arc<ClientConfig>>,
+    _shutdown: oneshot::Sender<()>,
+}
+
+struct Shared {
+    // `None` echoes the request.
+    responses: Option<Mutex<VecDeque<Response<Vec<u8>>>>>,
+    requests: Mutex<Vec<Request<Vec<u8>>>>,
+}
+
+impl TestServer {
+    /// Answers every request with `200 OK` and the request body, with the `Content-Type` of the request.
+    pub fn echo() -> io::Result<Self> {
+        Self::start(None, false)
+    }
+    /// Answers requests with `responses` in order. Once only one is left, it answers all further requests.
+    pub fn with_responses(responses: Vec<Response<Vec<u8>>>) -> io::Result<Self> {
+        Self::start(Some(responses), false)
+    }
+    /// Like [`echo`](TestServer::echo), but over TLS with a self-signed certificate for `localhost`, which
+    /// [`client_config`](TestServer::client_config) trusts.
+    #[cfg(feature = "tls")]
+    pub fn echo_tls() -> io::Result<Self> {
+        Self::start(None, true)
+    }
+    /// Like [`with_responses`](TestServer::with_responses), but over TLS, see [`echo_tls`](TestServer::echo_tls).
+    #[cfg(feature = "tls")]
+    pub fn with_responses_tls(responses: Vec<Response<Vec<u8>>>) -> io::Result<Self> {
+        Self::start(Some(responses), true)
+    }
+    #[cfg_attr(not(feature = "tls"), allow(unused_variables))]
+    fn start(responses: Option<Vec<Response<Vec<u8>>>>, tls: bool) -> io::Result<Self> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let listener = async_net::TcpListener::try_from(listener)?;
+        let shared = Arc::new(Shared {
+            responses: responses.map(|responses| Mutex::new(responses.into())),
+            requests: Mutex::default(),
+        });
+        #[cfg(feature = "tls")]
+        let (acceptor, client_config) = match tls {
+            true => {
+                let (acceptor, client_config) = self_signed()?;
+                (Some(acceptor), Some(client_config))
+            }
+            false => (None, None),
+        };
+        let (shutdown, stopped) = oneshot::channel();
+        let accept_shared = shared.clone();
+        thread::spawn(move || {
+            async_io::block_on(async move {
+                let mut stopped = stopped;
+                loop {
+                    let tcp = match select(pin!(listener.accept()), &mut stopped).await {
+                        Either::Left((Ok((tcp, _)), _)) => tcp,
+                        Either::Left((Err(_), _)) => continue,
+                        Either::Right(_) => return,
+                    };
+                    let shared = accept_shared.clone();
+                    #[cfg(feature = "tls")]
+                    let acceptor = acceptor.clone();
+                    thread::spawn(move || {
+                        async_io::block_on(async move {
+                            #[cfg(feature = "tls")]
+                            if let Some(acceptor) = acceptor {
+                                if let Ok(tls) = acceptor.accept(tcp).await {
+                                    serve(tls, &shared).await;
+                                }
+                                return;
+                            }
+                            serve(tcp, &shared).await;
+                        })
+                    });
+                }
+            })
+        });
+        Ok(Self {
+            addr,
+            shared,
+            #[cfg(feature = "tls")]
+            client_config,
+            _shutdown: shutdown,
+        })
+    }
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+    /// The URI of `path` on this server. TLS servers are addressed as `localhost`, the name in their certificate.
+    pub fn uri(&self, path: &str) -> Uri {
+        #[cfg(feature = "tls")]
+        if self.client_config.is_some() {
+            return format!("https://localhost:{}{path}", self.addr.port()).parse().unwrap();
+        }
+        format!("http://{}{path}", self.addr).parse().unwrap()
+    }
+    /// A client config trusting the certificate of a TLS server, `None` for plain TCP.
+    #[cfg(feature = "tls")]
+    pub fn client_config(&self) -> Option<Arc<ClientConfig>> {
+        self.client_config.clone()
+    }
+    /// The requests received so far, with their decoded bodies.
+    pub fn requests(&self) -> Vec<Request<Vec<u8>>> {
+        self.shared.requests.lock().unwrap().clone()
+    }
+}
+
+#[cfg(feature = "tls")]
+fn self_signed() -> io::Result<(TlsAcceptor, Arc<ClientConfig>)> {
+    use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).map_err(io::Error::other)?;
+    let der = CertificateDer::from(cert.serialize_der().map_err(io::Error::other)?);
+    let key = PrivateKeyDer::Pkcs8(cert.serialize_private_key_der().into());
+    let server_config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(vec![der.clone()], key)
+        .map_err(io::Error::other)?;
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(der).map_err(io::Error::other)?;
+    Ok((TlsAcceptor::from(Arc::new(server_config)), crate::client_config_with_roots(roots)))
+}
+
+// Serves requests until the client closes the connection or sends something that isn't a valid request.
+async fn serve(mut io: impl AsyncRead + AsyncWrite + Unpin, shared: &Shared) {
+    loop {
+        let Ok((_, head)) = RequestHead::decode(&mut io).await else { return };
+        let headers = head.headers().clone();
+        // Requests without framing headers have no body.
+        let body_state = match headers.contains_key(header::CONTENT_LENGTH) || headers.contains_key(header::TRANSFER_ENCODING) {
+            true => match BodyDecodeState::from_headers(&headers) {
+                Ok(state) => state,
+                Err(_) => return,
+            },
+            false => BodyDecodeState::new(Some(0)),
+        };
+        let mut body = Vec::new();
+        if body_state.into_async_read(&mut io).read_to_end(&mut body).await.is_err() {
+            return;
+        }
+        let mut request = Request::new(body);
+        *request.method_mut() = head.method();
+        *request.uri_mut() = head.uri().clone();
+        *request.version_mut() = head.version();
+        *request.headers_mut() = headers;
+        let response = shared.respond(&request);
+        let close = request
+            .headers()
+            .get_all(header::CONNECTION)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|token| token.trim().eq_ignore_ascii_case("close"));
+        let head_only = request.method() == Method::HEAD;
+        shared.requests.lock().unwrap().push(request);
+        if io.write_all(&encode_response(&response, head_only, close)).await.is_err() || io.flush().await.is_err() || close {
+            return;
+        }
+    }
+}
+
+impl Shared {
+    fn respond(&self, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+        let Some(responses) = &self.responses else {
+            let mut response = Response::new(request.body().clone());
+            if let Some(content_type) = request.headers().get(header::CONTENT_TYPE) {
+                response.headers_mut().insert(header::CONTENT_TYPE, content_type.clone());
+            }
+            return response;
+        };
+        let mut responses = responses.lock().unwrap();
+        if responses.len() > 1 {
+            return responses.pop_front().unwrap();
+        }
+        responses.front().cloned().unwrap_or_default()
+    }
+}
+
+// The body is always framed with `Content-Length`, whatever framing headers the response has.
+fn encode_response(response: &Response<Vec<u8>>, head_only: bool, close: bool) -> Vec<u8> {
+    let status = response.status();
+    let mut bytes = format!("HTTP/1.1 {} {}\r\n", status.as_str(), status.canonical_reason().unwrap_or("")).into_bytes();
+    for (name, value) in response.headers() {
+        if name == header::CONTENT_LENGTH || name == header::TRANSFER_ENCODING {
+            continue;
+        }
+        bytes.extend_from_slice(name.as_str().as_bytes());
+        bytes.extend_from_slice(b": ");
+        bytes.extend_from_slice(value.as_bytes());
+        bytes.extend_from_slice(b"\r\n");
+    }
+    bytes.extend_from_slice(format!("content-length: {}\r\n", response.body().len()).as_bytes());
+    if close {
+        bytes.extend_from_slice(b"connection: close\r\n");
+    }
+    bytes.extend_from_slice(b"\r\n");
+    if !head_only {
+        bytes.extend_from_slice(response.body());
+    }
+    bytes
+}
@@ -0,0 +1,646 @@
+use std::{
+    future::Future,
+    io, mem,
+    pin::Pin,
+    str,
+    sync::{Arc, Mutex},
+    task::{ready, Context, Poll, Wake, Waker},
+    time::Duration,
+};
+
+use async_io::Timer;
+use futures::{AsyncRead, AsyncWrite};
+
+use super::frame::{encode_frame, FrameHead, WsCloseFrame, WsOpcode};
+use super::{WsConnectionError, WsMessageKind};
+use crate::Transport;
+
+// Payload bytes a writer buffers before sending them as a frame.
+const FRAME_SIZE: usize = 1300;
+// After this long without receiving anything the peer is pinged, and if it stays silent for as long again the connection
+// fails. Also bounds the wait for the peer's close frame when pings are disabled.
+const PING_INTERVAL: Duration = Duration::from_secs(10);
+
+/// State of a connection, shared by the connection, its senders, readers and writers.
+pub(crate) struct Shared {
+    state: Mutex<State>,
+    wakers: Arc<Wakers>,
+}
+
+// Every task polling the connection is woken when the transport becomes ready, since e.g. a writer may have to wait for
+// a reader to make progress with the transport. The transport itself only keeps the waker it was polled with last.
+#[derive(Default)]
+struct Wakers(Mutex<Vec<Waker>>);
+
+impl Wake for Wakers {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+    fn wake_by_ref(self: &Arc<Self>) {
+        for waker in mem::take(&mut *self.0.lock().unwrap()) {
+            waker.wake();
+        }
+    }
+}
+
+struct State {
+    transport: Transport,
+    err: Option<Arc<WsConnectionError>>,
+    // Set when other tasks may be able to make progress.
+    notify: bool,
+    buf: Box<[u8]>,
+    start: usize,
+    end: usize,
+    message: Option<Message>,
+    ping_interval: Option<Duration>,
+    idle: Option<(Timer, bool)>,
+    received_close: Option<WsCloseFrame>,
+    out: Vec<u8>,
+    writer: Option<Writer>,
+    close_sent: bool,
+    shut_down: bool,
+}
+
+// The message being received.
+struct Message {
+    kind: WsMessageKind,
+    attach: Attach,
+    // Payload left in the current frame, `None` between frames.
+    remaining: Option<u64>,
+    fin: bool,
+    utf8: Utf8,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Attach {
+    // Not yet handed out as a reader.
+    Pending,
+    Reader,
+    Discard,
+}
+
+// The message being sent.
+struct Writer {
+    opcode: WsOpcode,
+    buf: Vec<u8>,
+}
+
+enum Incoming {
+    // A message started and waits to be handed out.
+    Start,
+    // Payload of the attached message is buffered.
+    Data,
+    // The attached message is complete.
+    End,
+    Closed,
+}
+
+// Incremental validation of text messages, which may split characters across frames or reads.
+#[derive(Default)]
+struct Utf8 {
+    incomplete: Vec<u8>,
+}
+
+impl Utf8 {
+    fn push(&mut self, mut bytes: &[u8]) -> bool {
+        if !self.incomplete.is_empty() {
+            let before = self.incomplete.len();
+            let take = bytes.len().min(4 - before);
+            self.incomplete.extend_from_slice(&bytes[..take]);
+            let valid = match str::from_utf8(&self.incomplete) {
+                Ok(_) => before + take,
+                Err(err) if err.valid_up_to() >= before => err.valid_up_to(),
+                Err(err) => return err.error_len().is_none(),
+            };
+            bytes = &bytes[valid - before..];
+            self.incomplete.clear();
+        }
+        match str::from_utf8(bytes) {
+            Ok(_) => true,
+            Err(err) if err.error_len().is_none() => {
+                self.incomplete = bytes[err.valid_up_to()..].to_vec();
+                true
+            }
+            Err(_) => false,
+        }
+    }
+    fn is_complete(&self) -> bool {
+        self.incomplete.is_empty()
+    }
+}
+
+impl Shared {
+    pub(crate) fn new(transport: Transport) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(State {
+                transport,
+                err: None,
+                notify: false,
+                buf: vec![0; 4096].into_boxed_slice(),
+                start: 0,
+                end: 0,
+                message: None,
+                ping_interval: Some(PING_INTERVAL),
+                idle: None,
+                received_close: None,
+                out: Vec::new(),
+                writer: None,
+                close_sent: false,
+                shut_down: false,
+            }),
+            wakers: Arc::default(),
+        })
+    }
+    fn poll_with<R>(&self, cx: &mut Context<'_>, f: impl FnOnce(&mut State, &mut Context<'_>) -> Poll<R>) -> Poll<R> {
+        {
+            let mut wakers = self.wakers.0.lock().unwrap();
+            if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                wakers.push(cx.waker().clone());
+            }
+        }
+        let waker = Waker::from(self.wakers.clone());
+        let mut state = self.state.lock().unwrap();
+        let poll = f(&mut state, &mut Context::from_waker(&waker));
+        if mem::take(&mut state.notify) {
+            drop(state);
+            self.wakers.wake_by_ref();
+        }
+        poll
+    }
+    // For drop handlers, which have no context: makes progress where possible and wakes everyone else.
+    fn with_state(&self, f: impl FnOnce(&mut State, &mut Context<'_>)) {
+        let waker = Waker::from(self.wakers.clone());
+        f(&mut self.state.lock().unwrap(), &mut Context::from_waker(&waker));
+        self.wakers.wake_by_ref();
+    }
+    pub(crate) fn err(&self) -> Option<Arc<WsConnectionError>> {
+        self.state.lock().unwrap().err.clone()
+    }
+    pub(crate) fn set_ping_interval(&self, ping_interval: Option<Duration>) {
+        let mut state = self.state.lock().unwrap();
+        state.ping_interval = ping_interval;
+        state.idle = None;
+    }
+    pub(crate) fn received_close(&self) -> Option<WsCloseFrame> {
+        self.state.lock().unwrap().received_close.clone()
+    }
+    pub(crate) fn poll_next_reader(self: &Arc<Self>, cx: &mut Context<'_>) -> Poll<Option<WsMessageReader>> {
+        self.poll_with(cx, |state, cx| {
+            // The previous message has to be read or dropped first.
+            if state.message.as_ref().is_some_and(|message| message.attach == Attach::Reader) {
+                return Poll::Pending;
+            }
+            match ready!(state.poll_incoming(cx)) {
+                Incoming::Start => {
+                    let message = state.message.as_mut().unwrap();
+                    message.attach = Attach::Reader;
+                    Poll::Ready(Some(WsMessageReader {
+                        kind: message.kind,
+                        shared: Some(self.clone()),
+                    }))
+                }
+                Incoming::Closed => {
+                    ready!(state.poll_shutdown(cx));
+                    Poll::Ready(None)
+                }
+                Incoming::Data | Incoming::End => unreachable!("no reader is attached"),
+            }
+        })
+    }
+    pub(crate) fn poll_close(&self, cx: &mut Context<'_>, frame: &WsCloseFrame) -> Poll<Result<(), Arc<WsConnectionError>>> {
+        self.poll_with(cx, |state, cx| {
+            if !state.close_sent && state.err.is_none() {
+                state.queue_close(frame);
+            }
+            // Whatever the peer sends until it echoes the close frame is discarded.
+            loop {
+                if let Some(message) = &mut state.message {
+                    if message.attach != Attach::Discard {
+                        message.attach = Attach::Discard;
+                        state.notify = true;
+                    }
+                }
+                if let Incoming::Closed = ready!(state.poll_incoming(cx)) {
+                    break;
+                }
+            }
+            ready!(state.poll_shutdown(cx));
+            match &state.err {
+                Some(err) => Poll::Ready(Err(err.clone())),
+                None => Poll::Ready(Ok(())),
+            }
+        })
+    }
+}
+
+impl State {
+    // Reads until the attached message has data or ends, a message starts, or the connection is closed. Control frames
+    // are handled on the way.
+    fn poll_incoming(&mut self, cx: &mut Context<'_>) -> Poll<Incoming> {
+        loop {
+            if self.err.is_some() || self.received_close.is_some() {
+                return Poll::Ready(Incoming::Closed);
+            }
+            if !self.out.is_empty() {
+                if let Poll::Ready(Err(err)) = self.poll_drain(cx) {
+                    self.fail(WsConnectionError::Io(err), cx);
+                    continue;
+                }
+            }
+            let buffered = (self.end - self.start) as u64;
+            if let Some(message) = &mut self.message {
+                match (message.attach, message.remaining) {
+                    (Attach::Pending, _) => return Poll::Ready(Incoming::Start),
+                    (Attach::Reader, Some(0)) if message.fin => return Poll::Ready(Incoming::End),
+                    (Attach::Discard, Some(0)) if message.fin => {
+                        let message = self.message.take().unwrap();
+                        if !message.utf8.is_complete() {
+                            self.fail(WsConnectionError::IncompleteUtf8, cx);
+                        }
+                        continue;
+                    }
+                    (_, Some(0)) => {
+                        message.remaining = None;
+                        continue;
+                    }
+                    (Attach::Reader, Some(_)) if buffered > 0 => return Poll::Ready(Incoming::Data),
+                    (Attach::Discard, Some(remaining)) if buffered > 0 => {
+                        let n = remaining.min(buffered) as usize;
+                        message.remaining = Some(remaining - n as u64);
+                        let skipped = &self.buf[self.start..self.start + n];
+                        self.start += n;
+                        // Skipped text is validated as well, the connection fails on invalid text whether it is read
+                        // or not.
+                        if let (WsMessageKind::Text, false) = (message.kind, message.utf8.push(skipped)) {
+                            self.fail(WsConnectionError::InvalidUtf8, cx);
+                        }
+                        continue;
+                    }
+                    (_, Some(_)) => {
+                        ready!(self.poll_fill(cx));
+                        continue;
+                    }
+                    (_, None) => {}
+                }
+            }
+            let (head, head_len) = match FrameHead::parse(&self.buf[self.start..self.end]) {
+                Ok(Some(head)) => head,
+                Ok(None) => {
+                    ready!(self.poll_fill(cx));
+                    continue;
+                }
+                Err(reason) => {
+                    self.fail_protocol(reason, cx);
+                    continue;
+                }
+            };
+            if head.mask.is_some() {
+                self.fail_protocol("server sent a masked frame", cx);
+                continue;
+            }
+            if head.opcode.is_control() {
+                let len = head_len + head.len as usize;
+                if self.end - self.start < len {
+                    ready!(self.poll_fill(cx));
+                    continue;
+                }
+                let payload = self.buf[self.start + head_len..self.start + len].to_vec();
+                self.start += len;
+                self.on_control(head.opcode, &payload, cx);
+                continue;
+            }
+            self.start += head_len;
+            match (head.opcode, &mut self.message) {
+                (WsOpcode::Continuation, Some(message)) => {
+                    message.remaining = Some(head.len);
+                    message.fin = head.fin;
+                }
+                (WsOpcode::Text | WsOpcode::Binary, None) => {
+                    self.message = Some(Message {
+                        kind: match head.opcode {
+                            WsOpcode::Text => WsMessageKind::Text,
+                            _ => WsMessageKind::Binary,
+                        },
+                        attach: Attach::Pending,
+                        remaining: Some(head.len),
+                        fin: head.fin,
+                        utf8: Utf8::default(),
+                    })
+                }
+                (WsOpcode::Continuation, None) => self.fail_protocol("continuation frame without a message", cx),
+                _ => self.fail_protocol("message started before the previous one ended", cx),
+            }
+        }
+    }
+    fn on_control(&mut self, opcode: WsOpcode, payload: &[u8], cx: &mut Context<'_>) {
+        match opcode {
+            WsOpcode::Ping if !self.close_sent => encode_frame(&mut self.out, true, WsOpcode::Pong, payload),
+            WsOpcode::Close => match WsCloseFrame::parse(payload) {
+                Ok(frame) => {
+                    if !self.close_sent {
+                        self.queue_close(&WsCloseFrame {
+                            code: frame.code,
+                            reason: String::new(),
+                        });
+                    }
+                    self.received_close = Some(frame);
+                    self.notify = true;
+                }
+                Err(err) => self.fail(err, cx),
+            },
+            _ => {}
+        }
+    }
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.start > 0 {
+            self.buf.copy_within(self.start..self.end, 0);
+            self.end -= self.start;
+            self.start = 0;
+        }
+        match Pin::new(&mut self.transport).poll_read(cx, &mut self.buf[self.end..]) {
+            Poll::Ready(Ok(0)) => self.fail(WsConnectionError::Io(io::ErrorKind::UnexpectedEof.into()), cx),
+            Poll::Ready(Ok(n)) => {
+                self.end += n;
+                self.idle = None;
+            }
+            Poll::Ready(Err(err)) => self.fail(WsConnectionError::Io(err), cx),
+            Poll::Pending => return self.poll_idle(cx),
+        }
+        Poll::Ready(())
+    }
+    fn poll_idle(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let interval = match (self.ping_interval, self.close_sent) {
+            (Some(interval), _) => interval,
+            (None, true) => PING_INTERVAL,
+            (None, false) => return Poll::Pending,
+        };
+        let (timer, pinged) = self.idle.get_or_insert_with(|| (Timer::after(interval), false));
+        if Pin::new(timer).poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+        match *pinged || self.close_sent {
+            true => self.fail(WsConnectionError::Timeout, cx),
+            false => {
+                self.idle = Some((Timer::after(interval), true));
+                encode_frame(&mut self.out, true, WsOpcode::Ping, &[]);
+            }
+        }
+        Poll::Ready(())
+    }
+    // Writes out queued frames and flushes the transport.
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.out.is_empty() {
+            match ready!(Pin::new(&mut self.transport).poll_write(cx, &self.out))? {
+                0 => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                n => drop(self.out.drain(..n)),
+            }
+        }
+        Pin::new(&mut self.transport).poll_flush(cx)
+    }
+    // Once a close frame was sent, sends what is still queued and closes the transport. Errors are ignored, the peer may
+    // have closed it already.
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.close_sent && !self.shut_down {
+            if ready!(self.poll_drain(cx)).is_ok() {
+                let _ = ready!(Pin::new(&mut self.transport).poll_close(cx));
+            }
+            self.shut_down = true;
+        }
+        Poll::Ready(())
+    }
+    // A message being written is abandoned, the peer won't accept data frames after the close frame.
+    fn queue_close(&mut self, frame: &WsCloseFrame) {
+        encode_frame(&mut self.out, true, WsOpcode::Close, &frame.payload());
+        self.close_sent = true;
+        self.writer = None;
+        self.notify = true;
+    }
+    fn fail_protocol(&mut self, reason: &'static str, cx: &mut Context<'_>) {
+        self.fail(WsConnectionError::Protocol(reason), cx)
+    }
+    // Fails the connection. If the peer is at fault, it is told why with a close frame.
+    fn fail(&mut self, err: WsConnectionError, cx: &mut Context<'_>) {
+        let code = match err {
+            WsConnectionError::InvalidUtf8 | WsConnectionError::IncompleteUtf8 => Some(WsCloseFrame::INVALID_PAYLOAD),
+            WsConnectionError::Protocol(_) => Some(WsCloseFrame::PROTOCOL_ERROR),
+            WsConnectionError::Io(_) | WsConnectionError::Timeout => None,
+        };
+        if self.err.is_none() {
+            self.err = Some(Arc::new(err));
+            self.notify = true;
+        }
+        if let (Some(code), false) = (code, self.close_sent) {
+            self.queue_close(&WsCloseFrame { code, reason: String::new() });
+            let _ = self.poll_drain(cx);
+        }
+    }
+    fn emit_frame(&mut self, fin: bool) {
+        let writer = self.writer.as_mut().unwrap();
+        encode_frame(&mut self.out, fin, writer.opcode, &writer.buf);
+        writer.buf.clear();
+        writer.opcode = WsOpcode::Continuation;
+    }
+    fn check_writable(&self) -> io::Result<()> {
+        match self.err.is_some() || self.close_sent {
+            true => Err(io::ErrorKind::BrokenPipe.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Resolves to a writer for a new message once the previous one is complete, or to `None` if the connection is closed.
+pub struct WsSend {
+    kind: WsMessageKind,
+    shared: Arc<Shared>,
+}
+
+impl WsSend {
+    pub(crate) fn new(kind: WsMessageKind, shared: Arc<Shared>) -> Self {
+        Self { kind, shared }
+    }
+    pub fn kind(&self) -> WsMessageKind {
+        self.kind
+    }
+    pub fn err(&self) -> Option<Arc<WsConnectionError>> {
+        self.shared.err()
+    }
+}
+
+impl Future for WsSend {
+    type Output = Option<WsMessageWriter>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let kind = self.kind;
+        self.shared.poll_with(cx, |state, _| {
+            if state.check_writable().is_err() {
+                return Poll::Ready(None);
+            }
+            if state.writer.is_some() {
+                return Poll::Pending;
+            }
+            state.writer = Some(Writer {
+                opcode: match kind {
+                    WsMessageKind::Text => WsOpcode::Text,
+                    WsMessageKind::Binary => WsOpcode::Binary,
+                },
+                buf: Vec::with_capacity(FRAME_SIZE),
+            });
+            Poll::Ready(Some(WsMessageWriter {
+                kind,
+                shared: Some(self.shared.clone()),
+                finished: false,
+            }))
+        })
+    }
+}
+
+/// Writes a message, which is sent in frames of about 1.3 kB and whenever it is flushed. Closing the writer completes the
+/// message, dropping it does the same without waiting for the last frame to be written.
+pub struct WsMessageWriter {
+    kind: WsMessageKind,
+    shared: Option<Arc<Shared>>,
+    finished: bool,
+}
+
+impl WsMessageWriter {
+    pub fn kind(&self) -> WsMessageKind {
+        self.kind
+    }
+}
+
+impl AsyncWrite for WsMessageWriter {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let Some(shared) = &self.shared else {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        };
+        shared.poll_with(cx, |state, cx| {
+            state.check_writable()?;
+            if !state.out.is_empty() {
+                ready!(state.poll_drain(cx))?;
+            }
+            let writer = state.writer.as_mut().unwrap();
+            let n = buf.len().min(FRAME_SIZE - writer.buf.len());
+            writer.buf.extend_from_slice(&buf[..n]);
+            if writer.buf.len() == FRAME_SIZE {
+                state.emit_frame(false);
+            }
+            Poll::Ready(Ok(n))
+        })
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let Some(shared) = &self.shared else {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        };
+        shared.poll_with(cx, |state, cx| {
+            state.check_writable()?;
+            if !state.writer.as_ref().unwrap().buf.is_empty() {
+                state.emit_frame(false);
+            }
+            state.poll_drain(cx)
+        })
+    }
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        let Some(shared) = &this.shared else {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        };
+        ready!(shared.poll_with(cx, |state, cx| {
+            if !this.finished {
+                state.check_writable()?;
+                state.emit_frame(true);
+                state.writer = None;
+                state.notify = true;
+                this.finished = true;
+            }
+            state.poll_drain(cx)
+        }))?;
+        this.shared = None;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for WsMessageWriter {
+    fn drop(&mut self) {
+        if let (Some(shared), false) = (self.shared.take(), self.finished) {
+            shared.with_state(|state, cx| {
+                if state.check_writable().is_ok() {
+                    state.emit_frame(true);
+                    state.writer = None;
+                    let _ = state.poll_drain(cx);
+                }
+            });
+        }
+    }
+}
+
+/// Reads the payload of a message, reassembled from its frames. Dropping the reader before the end skips the rest of the
+/// message.
+pub struct WsMessageReader {
+    kind: WsMessageKind,
+    shared: Option<Arc<Shared>>,
+}
+
+impl WsMessageReader {
+    pub fn kind(&self) -> WsMessageKind {
+        self.kind
+    }
+}
+
+impl AsyncRead for WsMessageReader {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let Some(shared) = &self.shared else { return Poll::Ready(Ok(0)) };
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let n = ready!(shared.poll_with(cx, |state, cx| -> Poll<io::Result<usize>> {
+            // A reader is detached when the connection is being closed.
+            if state.message.as_ref().map(|message| message.attach) != Some(Attach::Reader) {
+                return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+            }
+            match ready!(state.poll_incoming(cx)) {
+                Incoming::Data => {
+                    let message = state.message.as_mut().unwrap();
+                    let remaining = message.remaining.unwrap();
+                    let n = buf.len().min(state.end - state.start).min(remaining.try_into().unwrap_or(usize::MAX));
+                    buf[..n].copy_from_slice(&state.buf[state.start..state.start + n]);
+                    state.start += n;
+                    message.remaining = Some(remaining - n as u64);
+                    if let WsMessageKind::Text = message.kind {
+                        if !message.utf8.push(&buf[..n]) {
+                            state.fail(WsConnectionError::InvalidUtf8, cx);
+                            return Poll::Ready(Err(io::ErrorKind::InvalidData.into()));
+                        }
+                    }
+                    Poll::Ready(Ok(n))
+                }
+                Incoming::End => {
+                    let message = state.message.take().unwrap();
+                    state.notify = true;
+                    if !message.utf8.is_complete() {
+                        state.fail(WsConnectionError::IncompleteUtf8, cx);
+                        return Poll::Ready(Err(io::ErrorKind::InvalidData.into()));
+                    }
+                    Poll::Ready(Ok(0))
+                }
+                Incoming::Closed => Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+                Incoming::Start => unreachable!("the attached message didn't end"),
+            }
+        }))?;
+        if n == 0 {
+            self.shared = None;
+        }
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl Drop for WsMessageReader {
+    fn drop(&mut self) {
+        if let Some(shared) = self.shared.take() {
+            shared.with_state(|state, _| {
+                if let Some(message) = state.message.as_mut().filter(|message| message.attach == Attach::Reader) {
+                    message.attach = Attach::Discard;
+                }
+            });
+        }
+    }
+}
@@ -1,7 +1,7 @@
-use std::sync::Arc;
+use std::{io, sync::Arc};
 
 use crate::HttpError;
-use http::uri::InvalidUri;
+use http::{uri::InvalidUri, HeaderValue};
 use thiserror::Error;
 
 #[derive(Error, Debug, Clone)]
@@ -10,14 +10,60 @@ pub enum WsConnectError {
     InvalidUpgradeRequest,
     #[error("invalid websocket upgrade response")]
     InvalidUpgradeResponse(Arc<http::Response<Box<dyn std::fmt::Debug + Send + Sync>>>),
+    /// The server switched protocols, but its `Sec-WebSocket-Accept` doesn't match the key of the request.
+    #[error("websocket handshake failed: expected Sec-WebSocket-Accept {expected:?}, got {received:?}")]
+    HandshakeFailed { expected: String, received: Option<HeaderValue> },
     #[error("invalid uri: {0:?}")]
-    InvalidUrl(Arc<InvalidUri>),
+    InvalidUrl(#[source] Arc<InvalidUri>),
     #[error("websocket upgrade request error: {0:?}")]
     UpgradeRequestHttpError(#[from] HttpError),
     #[error("nknown gloo error: {0:?}")]
     Other(()),
 }
 
+/// Why [`WsConnection::next_message`](super::WsConnection::next_message) couldn't return a message.
+#[derive(Error, Debug, Clone)]
+pub enum WsMessageError {
+    /// The message is longer than the limit set with
+    /// [`with_max_message_size`](super::WsConnection::with_max_message_size). The rest of it is skipped.
+    #[error("websocket message exceeds {limit} bytes")]
+    TooLarge { limit: usize },
+    #[error("websocket connection error: {0}")]
+    Connection(#[source] Arc<WsConnectionError>),
+    #[error("io error: {0:?}")]
+    Io(#[source] Arc<io::Error>),
+}
+
+/// Why a connection failed, see [`WsConnection::err`](super::WsConnection::err).
+#[derive(Error, Debug)]
+pub enum WsConnectionError {
+    #[error("invalid utf8 in text message or close reason")]
+    InvalidUtf8,
+    #[error("incomplete utf8 in text message")]
+    IncompleteUtf8,
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("protocol error: {0}")]
+    Protocol(&'static str),
+    #[error("timeout")]
+    Timeout,
+}
+
+/// Why [`WsConnection::close`](super::WsConnection::close) failed.
+#[derive(Error, Debug, Clone)]
+pub enum WsCloseError {
+    /// The code is reserved or outside the ranges defined by RFC 6455, see
+    /// [`WsCloseFrame::is_valid_code`](super::WsCloseFrame::is_valid_code).
+    #[error("invalid close code {0}")]
+    InvalidCode(u16),
+    /// The reason doesn't fit into a close frame, which allows 123 bytes.
+    #[error("close reason of {0} bytes is too long")]
+    ReasonTooLong(usize),
+    /// The connection failed before the peer answered the close frame. The transport was closed anyway.
+    #[error("websocket connection error: {0}")]
+    Connection(#[source] Arc<WsConnectionError>),
+}
+
 impl From<InvalidUri> for WsConnectError {
     fn from(value: InvalidUri) -> Self {
         WsConnectError::InvalidUrl(Arc::new(value))
@@ -0,0 +1,193 @@
+use std::str;
+
+use super::WsConnectionError;
+
+/// The type of a frame, see [`WsFrameConnection`](super::WsFrameConnection).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WsOpcode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl WsOpcode {
+    fn from_bits(bits: u8) -> Option<Self> {
+        Some(match bits {
+            0x0 => Self::Continuation,
+            0x1 => Self::Text,
+            0x2 => Self::Binary,
+            0x8 => Self::Close,
+            0x9 => Self::Ping,
+            0xA => Self::Pong,
+            _ => return None,
+        })
+    }
+    fn bits(self) -> u8 {
+        match self {
+            Self::Continuation => 0x0,
+            Self::Text => 0x1,
+            Self::Binary => 0x2,
+            Self::Close => 0x8,
+            Self::Ping => 0x9,
+            Self::Pong => 0xA,
+        }
+    }
+    /// Whether this is a close, ping or pong frame, which can't be fragmented and have at most 125 bytes of payload.
+    pub fn is_control(self) -> bool {
+        matches!(self, Self::Close | Self::Ping | Self::Pong)
+    }
+}
+
+/// A frame as read or written by a [`WsFrameConnection`](super::WsFrameConnection), with an unmasked payload.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WsFrame {
+    /// Whether this is the last frame of a message.
+    pub fin: bool,
+    pub opcode: WsOpcode,
+    pub payload: Vec<u8>,
+}
+
+impl WsFrame {
+    /// A frame with `fin` set, i.e. a complete message or control frame.
+    pub fn new(opcode: WsOpcode, payload: impl Into<Vec<u8>>) -> Self {
+        Self {
+            fin: true,
+            opcode,
+            payload: payload.into(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct FrameHead {
+    pub(crate) fin: bool,
+    pub(crate) opcode: WsOpcode,
+    pub(crate) mask: Option<[u8; 4]>,
+    pub(crate) len: u64,
+}
+
+impl FrameHead {
+    /// Parses the head at the start of `buf` and returns it with its encoded length, or `None` if `buf` is too short.
+    /// Extensions aren't supported, so reserved bits must be unset.
+    pub(crate) fn parse(buf: &[u8]) -> Result<Option<(Self, usize)>, &'static str> {
+        let [first, second, ..] = *buf else { return Ok(None) };
+        if first & 0x70 != 0 {
+            return Err("reserved bits are set");
+        }
+        let opcode = WsOpcode::from_bits(first & 0x0F).ok_or("unknown opcode")?;
+        let fin = first & 0x80 != 0;
+        let (mut head_len, len) = match second & 0x7F {
+            126 => (4, buf.get(2..4).map(|b| u16::from_be_bytes(b.try_into().unwrap()) as u64)),
+            127 => (10, buf.get(2..10).map(|b| u64::from_be_bytes(b.try_into().unwrap()))),
+            len => (2, Some(len as u64)),
+        };
+        let Some(len) = len else { return Ok(None) };
+        if len >> 63 != 0 {
+            return Err("invalid payload length");
+        }
+        if opcode.is_control() && (!fin || len > 125) {
+            return Err("control frames can't be fragmented or longer than 125 bytes");
+        }
+        let mask = match second & 0x80 != 0 {
+            true => {
+                let Some(mask) = buf.get(head_len..head_len + 4) else { return Ok(None) };
+                head_len += 4;
+                Some(mask.try_into().unwrap())
+            }
+            false => None,
+        };
+        Ok(Some((Self { fin, opcode, mask, len }, head_len)))
+    }
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        out.push((self.fin as u8) << 7 | self.opcode.bits());
+        let mask_bit = (self.mask.is_some() as u8) << 7;
+        match self.len {
+            0..=125 => out.push(mask_bit | self.len as u8),
+            126..=0xFFFF => {
+                out.push(mask_bit | 126);
+                out.extend_from_slice(&(self.len as u16).to_be_bytes());
+            }
+            _ => {
+                out.push(mask_bit | 127);
+                out.extend_from_slice(&self.len.to_be_bytes());
+            }
+        }
+        if let Some(mask) = self.mask {
+            out.extend_from_slice(&mask);
+        }
+    }
+}
+
+/// Masks or unmasks `data`, which starts at `offset` within the payload.
+pub(crate) fn apply_mask(mask: [u8; 4], offset: u64, data: &mut [u8]) {
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte ^= mask[((offset + i as u64) % 4) as usize];
+    }
+}
+
+/// Appends a complete frame to `out`, masked with a random key as required for frames sent by clients.
+pub(crate) fn encode_frame(out: &mut Vec<u8>, fin: bool, opcode: WsOpcode, payload: &[u8]) {
+    let mask = fastrand::u32(..).to_ne_bytes();
+    let head = FrameHead {
+        fin,
+        opcode,
+        mask: Some(mask),
+        len: payload.len() as u64,
+    };
+    head.encode(out);
+    let start = out.len();
+    out.extend_from_slice(payload);
+    apply_mask(mask, 0, &mut out[start..]);
+}
+
+/// The status code and reason of a close frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WsCloseFrame {
+    /// [`NO_STATUS`](WsCloseFrame::NO_STATUS) if the frame had no body.
+    pub code: u16,
+    pub reason: String,
+}
+
+impl WsCloseFrame {
+    pub const NORMAL: u16 = 1000;
+    pub const GOING_AWAY: u16 = 1001;
+    pub const PROTOCOL_ERROR: u16 = 1002;
+    /// Only used to report close frames without a code, never sent.
+    pub const NO_STATUS: u16 = 1005;
+    /// Sent when a text message or close reason isn't valid UTF-8.
+    pub const INVALID_PAYLOAD: u16 = 1007;
+
+    /// Whether `code` may be sent in a close frame: the codes defined by RFC 6455 and its registry, except for those
+    /// reserved for reporting, and the ranges for libraries and applications.
+    pub fn is_valid_code(code: u16) -> bool {
+        matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999)
+    }
+    pub(crate) fn parse(payload: &[u8]) -> Result<Self, WsConnectionError> {
+        let (code, reason) = match payload {
+            [] => (Self::NO_STATUS, ""),
+            [_] => return Err(WsConnectionError::Protocol("close frame body is too short")),
+            [high, low, reason @ ..] => {
+                let code = u16::from_be_bytes([*high, *low]);
+                if !Self::is_valid_code(code) {
+                    return Err(WsConnectionError::Protocol("invalid close code"));
+                }
+                (code, str::from_utf8(reason).map_err(|_| WsConnectionError::InvalidUtf8)?)
+            }
+        };
+        Ok(Self {
+            code,
+            reason: reason.to_string(),
+        })
+    }
+    pub(crate) fn payload(&self) -> Vec<u8> {
+        if self.code == Self::NO_STATUS {
+            return Vec::new();
+        }
+        let mut payload = self.code.to_be_bytes().to_vec();
+        payload.extend_from_slice(self.reason.as_bytes());
+        payload
+    }
+}
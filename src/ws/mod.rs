@@ -2,29 +2,40 @@ use std::{
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
+    time::Duration,
 };
 
-use async_ws::{
-    connection::WsConfig,
-    http::{check_upgrade_response, is_upgrade_request, upgrade_request},
-};
-use futures::{AsyncReadExt, Stream};
-use http::Response;
+use async_ws::http::{check_upgrade_response, is_upgrade_request, upgrade_request};
+use futures::{future::poll_fn, AsyncReadExt, AsyncWriteExt, Stream, StreamExt};
+use http::{HeaderValue, Response, StatusCode};
+use ring::digest::{digest, SHA1_FOR_LEGACY_USE_ONLY};
 
 use crate::{RequestSend, Transport};
 
+mod connection;
 mod error;
+mod frame;
+mod raw;
+
+pub use connection::{WsMessageReader, WsMessageWriter, WsSend};
+pub use error::*;
+pub use frame::{WsCloseFrame, WsFrame, WsOpcode};
+pub use raw::WsFrameConnection;
 
-use error::*;
+use connection::Shared;
 
 pub type WsMessageKind = async_ws::message::WsMessageKind;
-pub type WsSend = async_ws::connection::WsSend<Transport>;
-pub type WsConnectionError = async_ws::connection::WsConnectionError;
-pub type WsMessageReader = async_ws::connection::WsMessageReader<Transport>;
-pub type WsMessageWriter = async_ws::connection::WsMessageWriter<Transport>;
 
 pub struct WsConnection {
-    inner: async_ws::connection::WsConnection<Transport>,
+    shared: Arc<Shared>,
+    max_message_size: Option<usize>,
+}
+
+/// A complete message, as returned by [`WsConnection::next_message`]. Text messages are valid UTF-8.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WsMessage {
+    Text(String),
+    Binary(Vec<u8>),
 }
 
 impl WsConnection {
@@ -39,30 +50,41 @@ impl WsConnection {
         Self::connect(&request).await
     }
     pub async fn connect(request: &http::Request<impl AsRef<[u8]>>) -> Result<Self, WsConnectError> {
-        if !is_upgrade_request(request) {
-            return Err(WsConnectError::InvalidUpgradeRequest);
-        }
-        let response = RequestSend::new(request).await?;
-        if !check_upgrade_response(request, &response) {
-            let (head, body_reader) = response.into_parts();
-            let mut buf = Vec::new();
-            let result = body_reader.take(1 << 14).read_to_end(&mut buf).await;
-            let result: Box<dyn std::fmt::Debug + Send + Sync> = match String::from_utf8(buf) {
-                Ok(str) => Box::new(result.map(move |_| str)),
-                Err(err) => Box::new(result.map(move |_| err.into_bytes())),
-            };
-            let response = Response::from_parts(head, result);
-            return Err(WsConnectError::InvalidUpgradeResponse(response.into()));
-        }
-        let transport = response.into_body().into_inner()?.1;
-        let inner = async_ws::connection::WsConnection::with_config(transport, WsConfig::client());
-        Ok(Self { inner })
+        Ok(Self {
+            shared: Shared::new(upgrade(request).await?),
+            max_message_size: None,
+        })
+    }
+    /// Limits the size of messages returned by [`next_message`](WsConnection::next_message), which fails with
+    /// [`WsMessageError::TooLarge`] for longer ones instead of buffering them. Messages read through the [`Stream`]
+    /// implementation aren't limited, since they aren't buffered.
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = Some(max_message_size);
+        self
     }
+    /// Sets how long the connection may stay silent before the peer is pinged. If it doesn't answer within the same
+    /// time, the connection fails with [`WsConnectionError::Timeout`]. Defaults to 10 seconds, `None` disables pings and
+    /// the timeout, except while waiting for the peer to answer a close frame.
+    pub fn with_ping_interval(self, ping_interval: Option<Duration>) -> Self {
+        self.shared.set_ping_interval(ping_interval);
+        self
+    }
+    /// A `GET` request with the upgrade headers and a random `Sec-WebSocket-Key`, to which the URI and further headers
+    /// can be added.
     pub fn connect_request_builder() -> http::request::Builder {
         upgrade_request()
     }
+    /// Like [`connect_request_builder`](WsConnection::connect_request_builder), but with the `Sec-WebSocket-Key` derived
+    /// from `nonce` instead of a random one, e.g. for tests that need a known key. Outside of tests the key should stay
+    /// random.
+    pub fn connect_request_builder_with_key(nonce: [u8; 16]) -> http::request::Builder {
+        let mut builder = upgrade_request();
+        let key = HeaderValue::try_from(base64::encode(nonce)).unwrap();
+        builder.headers_mut().unwrap().insert("Sec-WebSocket-Key", key);
+        builder
+    }
     pub fn send(&self, kind: WsMessageKind) -> WsSend {
-        self.inner.send(kind)
+        WsSend::new(kind, self.shared.clone())
     }
     pub fn send_text(&self) -> WsSend {
         self.send(WsMessageKind::Text)
@@ -71,7 +93,76 @@ impl WsConnection {
         self.send(WsMessageKind::Binary)
     }
     pub fn err(&self) -> Option<Arc<WsConnectionError>> {
-        self.inner.err()
+        self.shared.err()
+    }
+    /// Performs the closing handshake: sends a close frame with `code` and `reason`, waits for the peer to answer with
+    /// its own, then closes the transport. Messages arriving in the meantime are discarded, as is a message that is
+    /// still being written. If the peer closed the connection first, only the transport is closed.
+    pub async fn close(&self, code: u16, reason: &str) -> Result<(), WsCloseError> {
+        if !WsCloseFrame::is_valid_code(code) {
+            return Err(WsCloseError::InvalidCode(code));
+        }
+        if reason.len() > 123 {
+            return Err(WsCloseError::ReasonTooLong(reason.len()));
+        }
+        let frame = WsCloseFrame {
+            code,
+            reason: reason.to_string(),
+        };
+        poll_fn(|cx| self.shared.poll_close(cx, &frame)).await.map_err(WsCloseError::Connection)
+    }
+    /// The close frame received from the peer, once the connection was closed by either side. The [`Stream`] ends when
+    /// it arrives.
+    pub fn close_frame(&self) -> Option<WsCloseFrame> {
+        self.shared.received_close()
+    }
+    /// Receives the next message and reads it to the end. Fragmented messages are reassembled, control frames arriving
+    /// in between are answered as usual. Returns `None` once the connection is closed.
+    pub async fn next_message(&mut self) -> Option<Result<WsMessage, WsMessageError>> {
+        let mut reader = match self.next().await {
+            Some(reader) => reader,
+            None => return self.err().map(|err| Err(WsMessageError::Connection(err))),
+        };
+        let kind = reader.kind();
+        let mut buf = Vec::new();
+        let result = match self.max_message_size {
+            // One more byte than allowed tells a message at the limit apart from a longer one.
+            Some(limit) => reader.take(limit as u64 + 1).read_to_end(&mut buf).await,
+            None => reader.read_to_end(&mut buf).await,
+        };
+        if let Err(err) = result {
+            return Some(Err(match self.err() {
+                Some(err) => WsMessageError::Connection(err),
+                None => WsMessageError::Io(Arc::new(err)),
+            }));
+        }
+        if let Some(limit) = self.max_message_size.filter(|limit| buf.len() > *limit) {
+            return Some(Err(WsMessageError::TooLarge { limit }));
+        }
+        Some(Ok(match kind {
+            // The text was validated while reading.
+            WsMessageKind::Text => WsMessage::Text(String::from_utf8(buf).unwrap()),
+            WsMessageKind::Binary => WsMessage::Binary(buf),
+        }))
+    }
+    /// Sends a complete message. Payloads longer than a frame (about 1.3 kB) are split into continuation frames, the same
+    /// happens when a [`WsMessageWriter`] is flushed before it is closed.
+    pub async fn send_message(&self, message: &WsMessage) -> Result<(), WsMessageError> {
+        let (kind, data) = match message {
+            WsMessage::Text(text) => (WsMessageKind::Text, text.as_bytes()),
+            WsMessage::Binary(data) => (WsMessageKind::Binary, data.as_slice()),
+        };
+        let result = match self.send(kind).await {
+            Some(mut writer) => match writer.write_all(data).await {
+                Ok(()) => writer.close().await,
+                Err(err) => Err(err),
+            },
+            None => Err(std::io::ErrorKind::BrokenPipe.into()),
+        };
+        result.map_err(|err| match self.err() {
+            Some(err) => WsMessageError::Connection(err),
+            None => WsMessageError::Io(Arc::new(err)),
+        })
     }
 }
 
@@ -79,6 +170,44 @@ impl Stream for WsConnection {
     type Item = WsMessageReader;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        Pin::new(&mut self.get_mut().inner).poll_next(cx)
+        self.shared.poll_next_reader(cx)
+    }
+}
+
+// Sends the upgrade request and checks the response, returning the transport to run the protocol on.
+async fn upgrade(request: &http::Request<impl AsRef<[u8]>>) -> Result<Transport, WsConnectError> {
+    if !is_upgrade_request(request) {
+        return Err(WsConnectError::InvalidUpgradeRequest);
+    }
+    let response = RequestSend::new(request).await?;
+    if response.status() == StatusCode::SWITCHING_PROTOCOLS {
+        let key = request.headers().get("Sec-WebSocket-Key").unwrap();
+        let expected = accept_key(key.as_bytes());
+        let received = response.headers().get("Sec-WebSocket-Accept");
+        if received.map(HeaderValue::as_bytes) != Some(expected.as_bytes()) {
+            return Err(WsConnectError::HandshakeFailed {
+                expected,
+                received: received.cloned(),
+            });
+        }
     }
+    if !check_upgrade_response(request, &response) {
+        let (head, body_reader) = response.into_parts();
+        let mut buf = Vec::new();
+        let result = body_reader.take(1 << 14).read_to_end(&mut buf).await;
+        let result: Box<dyn std::fmt::Debug + Send + Sync> = match String::from_utf8(buf) {
+            Ok(str) => Box::new(result.map(move |_| str)),
+            Err(err) => Box::new(result.map(move |_| err.into_bytes())),
+        };
+        let response = Response::from_parts(head, result);
+        return Err(WsConnectError::InvalidUpgradeResponse(response.into()));
+    }
+    Ok(response.into_body().into_inner()?)
+}
+
+// The `Sec-WebSocket-Accept` a server has to answer `key` with, see RFC 6455 section 4.2.2.
+fn accept_key(key: &[u8]) -> String {
+    let mut input = key.to_vec();
+    input.extend_from_slice(b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11");
+    base64::encode(digest(&SHA1_FOR_LEGACY_USE_ONLY, &input))
 }
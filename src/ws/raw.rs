@@ -0,0 +1,68 @@
+use futures::{io::BufReader, AsyncReadExt, AsyncWriteExt};
+
+use super::frame::{encode_frame, FrameHead};
+use super::{upgrade, WsConnectError, WsConnectionError, WsFrame};
+use crate::Transport;
+
+/// A WebSocket connection exchanging single frames instead of messages, for protocol tests or extensions that need
+/// control over opcodes and fragmentation.
+///
+/// Nothing is handled automatically except masking, which is applied to every written frame: pings aren't answered,
+/// fragments aren't reassembled and close frames are returned like any other frame.
+pub struct WsFrameConnection {
+    transport: BufReader<Transport>,
+}
+
+impl WsFrameConnection {
+    /// Performs the same handshake as [`WsConnection::connect`](super::WsConnection::connect).
+    pub async fn connect(request: &http::Request<impl AsRef<[u8]>>) -> Result<Self, WsConnectError> {
+        Ok(Self {
+            transport: BufReader::new(upgrade(request).await?),
+        })
+    }
+    /// Reads the next frame, `None` if the peer closed the transport between frames. Frames that can't be parsed or are
+    /// masked, which servers must not do, fail with [`WsConnectionError::Protocol`].
+    pub async fn read_frame(&mut self) -> Result<Option<WsFrame>, WsConnectionError> {
+        let mut head = Vec::with_capacity(14);
+        let head = loop {
+            if let Some((head, _)) = FrameHead::parse(&head).map_err(WsConnectionError::Protocol)? {
+                break head;
+            }
+            let mut byte = 0;
+            if self.transport.read(std::slice::from_mut(&mut byte)).await? == 0 {
+                return match head.is_empty() {
+                    true => Ok(None),
+                    false => Err(WsConnectionError::Io(std::io::ErrorKind::UnexpectedEof.into())),
+                };
+            }
+            head.push(byte);
+        };
+        if head.mask.is_some() {
+            return Err(WsConnectionError::Protocol("server sent a masked frame"));
+        }
+        // The payload is read as it arrives instead of allocating the announced length up front.
+        let mut payload = Vec::new();
+        (&mut self.transport).take(head.len).read_to_end(&mut payload).await?;
+        if (payload.len() as u64) < head.len {
+            return Err(WsConnectionError::Io(std::io::ErrorKind::UnexpectedEof.into()));
+        }
+        Ok(Some(WsFrame {
+            fin: head.fin,
+            opcode: head.opcode,
+            payload,
+        }))
+    }
+    /// Writes `frame` as given, masked with a random key. Frames violating the protocol, e.g. fragmented control frames,
+    /// are written as well.
+    pub async fn write_frame(&mut self, frame: &WsFrame) -> Result<(), WsConnectionError> {
+        let mut out = Vec::new();
+        encode_frame(&mut out, frame.fin, frame.opcode, &frame.payload);
+        self.transport.write_all(&out).await?;
+        self.transport.flush().await?;
+        Ok(())
+    }
+    /// Closes the transport, without a close frame unless one was written before.
+    pub async fn close(&mut self) -> Result<(), WsConnectionError> {
+        Ok(self.transport.close().await?)
+    }
+}
@@ -0,0 +1,63 @@
+use async_web_client::{Connection, HttpError, MockTransport};
+use futures::AsyncReadExt;
+
+fn read_chunked(body: &[u8]) -> Result<Vec<u8>, HttpError> {
+    let mut response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+    response.extend_from_slice(body);
+    smol::block_on(async {
+        let mut connection = Connection::from_transport(MockTransport::new(response).into());
+        let request = http::Request::get("http://example.com/").body(Vec::new()).unwrap();
+        let mut body = connection.send(&request).unwrap().await?.into_body();
+        let mut data = Vec::new();
+        body.read_to_end(&mut data).await?;
+        Ok(data)
+    })
+}
+
+#[test]
+fn chunk_extensions_are_ignored() {
+    let body = b"4;name=value\r\nWiki\r\n5 ; a=1;b=\"x;y\"\r\npedia\r\nE\t;ext\r\n in\r\n\r\nchunks.\r\n0;last\r\n\r\n";
+    assert_eq!(read_chunked(body).unwrap(), b"Wikipedia in\r\n\r\nchunks.");
+}
+
+#[test]
+fn malformed_chunk_sizes_are_rejected() {
+    let bodies: &[&[u8]] = &[
+        b"\r\nabc\r\n0\r\n\r\n",
+        b";ext\r\nabc\r\n0\r\n\r\n",
+        b"-3\r\nabc\r\n0\r\n\r\n",
+        b"+3\r\nabc\r\n0\r\n\r\n",
+        b"0x3\r\nabc\r\n0\r\n\r\n",
+        b"3g\r\nabc\r\n0\r\n\r\n",
+        b"3 3\r\nabc\r\n0\r\n\r\n",
+        b"\xff\r\nabc\r\n0\r\n\r\n",
+        b"10000000000000000\r\nabc\r\n0\r\n\r\n",
+        b"fffffffffffffffffffff\r\nabc\r\n0\r\n\r\n",
+    ];
+    for body in bodies {
+        let result = read_chunked(body);
+        assert!(
+            matches!(result, Err(HttpError::InvalidChunk(_))),
+            "{:?}: {result:?}",
+            String::from_utf8_lossy(body)
+        );
+    }
+}
+
+// Size lines starting with a byte that isn't a hex digit, followed by random bytes, must fail cleanly rather than panic or
+// be mistaken for a valid size.
+#[test]
+fn random_size_lines_are_rejected() {
+    let mut rng = fastrand::Rng::with_seed(383);
+    for _ in 0..500 {
+        let mut body = vec![b'z'];
+        body.extend((0..rng.usize(..12)).map(|_| rng.u8(..)).filter(|&b| b != b'\r' && b != b'\n'));
+        body.extend_from_slice(b"\r\nabc\r\n0\r\n\r\n");
+        let result = read_chunked(&body);
+        assert!(
+            matches!(result, Err(HttpError::InvalidChunk(_))),
+            "{:?}: {result:?}",
+            String::from_utf8_lossy(&body)
+        );
+    }
+}
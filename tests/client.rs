@@ -0,0 +1,92 @@
+mod common;
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use async_web_client::{BearerToken, Client, CredentialProvider, HttpError, TestServer, TimeoutPhase, Timeouts};
+
+fn connect(client: &Client, addr: std::net::SocketAddr) -> async_web_client::Connection {
+    smol::block_on(client.connect("http", &addr.ip().to_string(), addr.port())).unwrap()
+}
+
+#[test]
+fn connection_uses_client_options() {
+    let server = TestServer::with_responses(vec![http::Response::builder().status(404).body(b"missing".to_vec()).unwrap()]).unwrap();
+    let client = Client::new().error_for_status();
+    let mut connection = connect(&client, server.addr());
+    let request = http::Request::get(server.uri("/")).body(Vec::new()).unwrap();
+    let result = smol::block_on(connection.send(&request).unwrap());
+    assert!(matches!(result, Err(HttpError::Status { status, body }) if status == 404 && body == b"missing"));
+}
+
+#[test]
+fn connection_uses_client_timeouts() {
+    // The server accepts the request and never answers.
+    let addr = common::serve_once(|stream| {
+        std::thread::sleep(Duration::from_secs(2));
+        drop(stream);
+    });
+    let client = Client::new().with_timeouts(Timeouts {
+        first_byte: Some(Duration::from_millis(100)),
+        ..Timeouts::default()
+    });
+    let mut connection = connect(&client, addr);
+    let request = http::Request::get(format!("http://{addr}/")).body(Vec::new()).unwrap();
+    let result = smol::block_on(connection.send(&request).unwrap());
+    assert!(
+        matches!(result, Err(HttpError::Timeout(TimeoutPhase::FirstByte))),
+        "{:?}",
+        result.map(|_| ())
+    );
+}
+
+// Hands out `token-1`, `token-2`, ... without expiry, so the first token is cached for every later request.
+#[derive(Default)]
+struct CountingProvider(AtomicUsize);
+
+impl CredentialProvider for CountingProvider {
+    fn token(&self) -> Pin<Box<dyn Future<Output = Result<BearerToken, HttpError>> + Send + '_>> {
+        let n = self.0.fetch_add(1, Ordering::SeqCst) + 1;
+        Box::pin(async move {
+            Ok(BearerToken {
+                token: format!("token-{n}"),
+                expires_at: None,
+            })
+        })
+    }
+}
+
+#[test]
+fn credentials_on_client_and_connection_requests() {
+    let server = TestServer::echo().unwrap();
+    let provider = Arc::new(CountingProvider::default());
+    let client = Client::new().with_credentials(provider.clone());
+    let request = http::Request::get(server.uri("/")).body(Vec::new()).unwrap();
+    smol::block_on(async {
+        client.send(&request).await.unwrap();
+        let mut connection = client
+            .connect("http", &server.addr().ip().to_string(), server.addr().port())
+            .await
+            .unwrap();
+        connection.send(&request).unwrap().await.unwrap();
+        let with_header = http::Request::get(server.uri("/"))
+            .header("authorization", "Basic abc")
+            .body(Vec::new())
+            .unwrap();
+        client.send(&with_header).await.unwrap();
+    });
+    let authorization: Vec<_> = server
+        .requests()
+        .iter()
+        .map(|request| request.headers()["authorization"].clone())
+        .collect();
+    assert_eq!(authorization, ["Bearer token-1", "Bearer token-1", "Basic abc"]);
+    assert_eq!(provider.0.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,58 @@
+// Not every test file uses every helper.
+#![allow(dead_code)]
+
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    thread,
+};
+
+/// Accepts a single connection, reads the request head and hands the stream to `serve`. The connection is closed when
+/// `serve` returns.
+pub fn serve_once(serve: impl FnOnce(TcpStream) + Send + 'static) -> SocketAddr {
+    serve_once_on("127.0.0.1:0", serve)
+}
+
+/// Like [`serve_once`], listening on `addr`.
+pub fn serve_once_on(addr: &str, serve: impl FnOnce(TcpStream) + Send + 'static) -> SocketAddr {
+    let listener = TcpListener::bind(addr).unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        read_head(&mut stream);
+        serve(stream);
+    });
+    addr
+}
+
+/// Answers a single request with `response` in one write and closes the connection.
+pub fn respond_once(response: &'static [u8]) -> SocketAddr {
+    serve_once(move |mut stream| stream.write_all(response).unwrap())
+}
+
+pub fn read_head(stream: &mut TcpStream) -> Vec<u8> {
+    let mut head = Vec::new();
+    let mut byte = 0;
+    while !head.ends_with(b"\r\n\r\n") && stream.read(std::slice::from_mut(&mut byte)).unwrap() == 1 {
+        head.push(byte);
+    }
+    head
+}
+
+/// Accepts connections until the test ends and answers each request with the response `respond` returns for its head,
+/// closing the connection afterwards.
+pub fn serve_each(respond: impl Fn(&str) -> Vec<u8> + Send + Sync + 'static) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let respond = std::sync::Arc::new(respond);
+    thread::spawn(move || {
+        for mut stream in listener.incoming().map_while(Result::ok) {
+            let respond = respond.clone();
+            thread::spawn(move || {
+                let head = read_head(&mut stream);
+                let _ = stream.write_all(&respond(&String::from_utf8_lossy(&head)));
+            });
+        }
+    });
+    addr
+}
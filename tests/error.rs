@@ -0,0 +1,56 @@
+use std::{error::Error, io, net::TcpListener, sync::Arc};
+
+use async_web_client::{Client, HttpError, RequestSend, TransportError};
+
+fn chain<'a>(err: &'a (dyn Error + 'static)) -> Vec<&'a (dyn Error + 'static)> {
+    std::iter::successors(Some(err), |&err| err.source()).collect()
+}
+
+// Sources that are shared through an `Arc` are reported as the `Arc`.
+fn io_kind(err: &(dyn Error + 'static)) -> Option<io::ErrorKind> {
+    let err = err
+        .downcast_ref::<Arc<io::Error>>()
+        .map(|err| &**err)
+        .or_else(|| err.downcast_ref::<io::Error>())?;
+    Some(err.kind())
+}
+
+#[test]
+fn connect_error_exposes_io_error() {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let request = http::Request::get(format!("http://127.0.0.1:{port}/")).body(Vec::new()).unwrap();
+    let err = smol::block_on(RequestSend::new(&request)).err().unwrap();
+    let chain = chain(&err);
+    assert_eq!(chain.len(), 3, "{chain:?}");
+    assert!(matches!(chain[1].downcast_ref::<TransportError>(), Some(TransportError::TcpConnect(_))));
+    assert_eq!(io_kind(chain[2]), Some(io::ErrorKind::ConnectionRefused));
+}
+
+#[test]
+fn invalid_request_exposes_http_error() {
+    let err = smol::block_on(Client::new().request(http::Method::GET, "http://exa mple.com/").send())
+        .err()
+        .unwrap();
+    assert!(matches!(err, HttpError::InvalidRequest(_)));
+    let source = err.source().unwrap();
+    assert!(source.downcast_ref::<Arc<http::Error>>().is_some(), "{source:?}");
+}
+
+#[cfg(feature = "tls")]
+#[test]
+fn tls_handshake_exposes_rustls_error() {
+    // The self-signed certificate of the server isn't trusted by the default config.
+    let server = async_web_client::TestServer::echo_tls().unwrap();
+    let request = http::Request::get(server.uri("/")).body(Vec::new()).unwrap();
+    let err = smol::block_on(RequestSend::new(&request)).err().unwrap();
+    let source = err.source().unwrap().downcast_ref::<rustls::Error>();
+    assert!(matches!(source, Some(rustls::Error::InvalidCertificate(_))), "{err:?}");
+}
+
+#[test]
+fn io_error_round_trip_keeps_http_error() {
+    let err = io::Error::from(HttpError::ConnectionClosed);
+    let inner = err.get_ref().unwrap().downcast_ref::<HttpError>();
+    assert!(matches!(inner, Some(HttpError::ConnectionClosed)));
+    assert!(matches!(HttpError::from(err), HttpError::ConnectionClosed));
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use async_web_client::Client;
+
+// The server claims a huge length for the `HEAD` request but ignores ranges, so every segment gets the whole short body.
+#[test]
+fn huge_announced_length_falls_back() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counted = requests.clone();
+    let addr = common::serve_each(move |head| {
+        counted.fetch_add(1, Ordering::SeqCst);
+        match head.starts_with("HEAD ") {
+            true => format!("HTTP/1.1 200 OK\r\nAccept-Ranges: bytes\r\nContent-Length: {}\r\n\r\n", u64::MAX >> 1).into_bytes(),
+            false => b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort body".to_vec(),
+        }
+    });
+    let request = http::Request::get(format!("http://{addr}/file")).body(()).unwrap();
+    let body = smol::block_on(Client::new().download_parallel(&request, 1000)).unwrap();
+    assert_eq!(body, b"short body");
+    // The `HEAD` request, at most 32 segments and the fallback download.
+    assert_eq!(requests.load(Ordering::SeqCst), 1 + 32 + 1);
+}
+
+/// Answers `HEAD` requests with the length of `body` and range requests with the requested part of it, counting the
+/// requests.
+fn serve_ranges(body: &'static [u8], requests: Arc<AtomicUsize>) -> std::net::SocketAddr {
+    common::serve_each(move |head| {
+        requests.fetch_add(1, Ordering::SeqCst);
+        if head.starts_with("HEAD ") {
+            return format!("HTTP/1.1 200 OK\r\nAccept-Ranges: bytes\r\nContent-Length: {}\r\n\r\n", body.len()).into_bytes();
+        }
+        let range = head.lines().find_map(|line| line.strip_prefix("range: bytes=")).unwrap();
+        let (start, end) = range.split_once('-').unwrap();
+        let (start, end): (usize, usize) = (start.parse().unwrap(), end.parse().unwrap());
+        assert!(start <= end && end < body.len(), "invalid range {range}");
+        let mut response = format!(
+            "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {start}-{end}/{}\r\nContent-Length: {}\r\n\r\n",
+            body.len(),
+            end - start + 1
+        )
+        .into_bytes();
+        response.extend_from_slice(&body[start..=end]);
+        response
+    })
+}
+
+#[test]
+fn segments_are_reassembled() {
+    const BODY: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    let addr = serve_ranges(BODY, Arc::default());
+    let request = http::Request::get(format!("http://{addr}/file")).body(()).unwrap();
+    assert_eq!(smol::block_on(Client::new().download_parallel(&request, 5)).unwrap(), BODY);
+}
+
+// 10 bytes in 8 segments are 5 segments of 2 bytes, rounding up the segment length must not leave empty ranges behind.
+#[test]
+fn rounded_segments_stay_within_the_length() {
+    const BODY: &[u8] = b"0123456789";
+    let requests = Arc::new(AtomicUsize::new(0));
+    let addr = serve_ranges(BODY, requests.clone());
+    let request = http::Request::get(format!("http://{addr}/file")).body(()).unwrap();
+    assert_eq!(smol::block_on(Client::new().download_parallel(&request, 8)).unwrap(), BODY);
+    // The `HEAD` request and 5 segments, without a fallback download.
+    assert_eq!(requests.load(Ordering::SeqCst), 1 + 5);
+}
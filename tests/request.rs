@@ -0,0 +1,68 @@
+mod common;
+
+use std::{
+    io::{Read, Write},
+    sync::mpsc,
+    time::Duration,
+};
+
+use async_web_client::{Connection, MockTransport, RequestSend, ResponseExt};
+use futures::future::{select, Either};
+
+#[test]
+fn dropping_while_sending_body_closes_socket() {
+    let (closed, closed_rx) = mpsc::channel();
+    let addr = common::serve_once(move |mut stream| {
+        // The body is only read after the request was dropped, so the client is stuck writing it until then.
+        std::thread::sleep(Duration::from_millis(300));
+        let mut buf = vec![0; 64 * 1024];
+        let end = loop {
+            match stream.read(&mut buf) {
+                Ok(0) => break Ok(()),
+                Ok(_) => continue,
+                Err(err) => break Err(err.kind()),
+            }
+        };
+        closed.send(end).unwrap();
+    });
+    smol::block_on(async {
+        let request = http::Request::post(format!("http://{addr}/")).body(vec![0u8; 32 << 20]).unwrap();
+        let send = RequestSend::new(&request);
+        match select(send, smol::Timer::after(Duration::from_millis(100))).await {
+            Either::Left(_) => panic!("the request finished although the server never read the body"),
+            Either::Right((_, send)) => drop(send),
+        }
+    });
+    let end = closed_rx.recv_timeout(Duration::from_secs(5)).expect("the socket wasn't closed");
+    assert!(matches!(end, Ok(()) | Err(std::io::ErrorKind::ConnectionReset)), "{end:?}");
+}
+
+#[test]
+fn ipv6_literal_host() {
+    let addr = common::serve_once_on("[::1]:0", |mut stream| {
+        stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+    });
+    smol::block_on(async {
+        let request = http::Request::get(format!("http://[::1]:{}/", addr.port())).body(Vec::new()).unwrap();
+        let response = RequestSend::new(&request).await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+        assert_eq!(response.peer_addr(), Some(addr));
+    })
+}
+
+#[test]
+fn close_connection_keeps_other_tokens() {
+    smol::block_on(async {
+        let transport = MockTransport::new(&b"HTTP/1.1 204 No Content\r\n\r\n"[..]);
+        let written = transport.written();
+        let mut connection = Connection::from_transport(transport.into());
+        let request = http::Request::get("http://example.com/")
+            .header("connection", "Upgrade")
+            .body(Vec::new())
+            .unwrap();
+        connection.send(&request).unwrap().close_connection().await.unwrap();
+        let head = String::from_utf8(written.bytes()).unwrap();
+        let tokens: Vec<_> = head.lines().filter_map(|line| line.strip_prefix("connection: ")).collect();
+        assert_eq!(tokens, ["Upgrade", "close"], "{head}");
+    })
+}
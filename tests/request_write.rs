@@ -0,0 +1,141 @@
+mod common;
+
+use std::{
+    future::poll_fn,
+    io::{Read, Write},
+    pin::Pin,
+    sync::mpsc,
+    task::Poll,
+    time::Duration,
+};
+
+use async_web_client::{HttpError, MockTransport, RequestWrite};
+use futures::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use http::Version;
+
+const OK: &[u8] = b"HTTP/1.0 200 OK\r\nContent-Length: 2\r\n\r\nok";
+
+#[test]
+fn http_10_upload_with_length() {
+    smol::block_on(async {
+        let transport = MockTransport::new(OK);
+        let written = transport.written();
+        let request = http::Request::post("http://example.com/upload")
+            .header(http::header::CONTENT_LENGTH, "5")
+            .body(())
+            .unwrap();
+        let mut write = RequestWrite::on_stream(&request, transport).with_version(Version::HTTP_10);
+        write.write_all(b"hello").await.unwrap();
+        let (response, mut body) = write.response().await.unwrap();
+        assert_eq!(response.version(), Version::HTTP_10);
+        let mut text = String::new();
+        body.read_to_string(&mut text).await.unwrap();
+        assert_eq!(text, "ok");
+        let written = String::from_utf8(written.bytes()).unwrap();
+        assert!(written.starts_with("POST /upload HTTP/1.0\r\n"), "{written}");
+        assert!(written.contains("connection: close\r\n"), "{written}");
+        assert!(!written.contains("transfer-encoding"), "{written}");
+        assert!(written.ends_with("\r\n\r\nhello"), "{written}");
+    })
+}
+
+#[test]
+fn http_10_upload_without_length() {
+    smol::block_on(async {
+        let request = http::Request::post("http://example.com/upload").body(()).unwrap();
+        let mut write = RequestWrite::on_stream(&request, MockTransport::new(OK)).with_version(Version::HTTP_10);
+        let err = write.write_all(b"hello").await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+        assert!(matches!(write.response().await, Err(HttpError::UnsupportedVersion(Version::HTTP_10))));
+    })
+}
+
+// Reads a chunked request body after the head and answers it. Sends everything received after the head to `received`
+// once the client closes the connection.
+fn chunked_server(delay: Duration, received: mpsc::Sender<Vec<u8>>) -> std::net::SocketAddr {
+    common::serve_once(move |mut stream| {
+        std::thread::sleep(delay);
+        let mut body = Vec::new();
+        let mut buf = vec![0; 64 * 1024];
+        while !body.ends_with(b"\r\n0\r\n\r\n") && !body.starts_with(b"0\r\n\r\n") {
+            let n = stream.read(&mut buf).unwrap();
+            assert!(n > 0, "the body ended early");
+            body.extend_from_slice(&buf[..n]);
+        }
+        stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+        // Anything the client still sends, like a second terminator, ends up in `body` too.
+        stream.read_to_end(&mut body).unwrap();
+        received.send(body).unwrap();
+    })
+}
+
+fn chunk_sizes(mut body: &[u8]) -> Vec<usize> {
+    let mut sizes = Vec::new();
+    while !body.is_empty() {
+        let line = body.iter().position(|&b| b == b'\r').unwrap();
+        let size = usize::from_str_radix(std::str::from_utf8(&body[..line]).unwrap(), 16).unwrap();
+        sizes.push(size);
+        body = &body[line + 2 + size + 2..];
+    }
+    sizes
+}
+
+#[test]
+fn interrupted_close_writes_one_terminator() {
+    const LEN: usize = 32 << 20;
+    let (received, received_rx) = mpsc::channel();
+    let addr = chunked_server(Duration::from_millis(200), received);
+    smol::block_on(async {
+        let request = http::Request::post(format!("http://{addr}/")).body(()).unwrap();
+        // The whole body is buffered, so it's only sent while closing, which the server doesn't read at first.
+        let mut write = RequestWrite::start(&request).with_buffer_size(2 * LEN);
+        write.write_all(&vec![b'a'; LEN]).await.unwrap();
+        let mut pending = 0;
+        poll_fn(|cx| match Pin::new(&mut write).poll_close(cx) {
+            Poll::Pending => {
+                pending += 1;
+                Poll::Pending
+            }
+            ready => ready,
+        })
+        .await
+        .unwrap();
+        assert!(pending > 1, "close was only polled {pending} times");
+        write.close().await.unwrap();
+        let (response, _) = write.response().await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+    });
+    let body = received_rx.recv_timeout(Duration::from_secs(10)).unwrap();
+    let sizes = chunk_sizes(&body);
+    assert_eq!(sizes.iter().sum::<usize>(), LEN);
+    assert_eq!(sizes.iter().filter(|&&size| size == 0).count(), 1);
+    assert_eq!(sizes.last(), Some(&0));
+}
+
+#[test]
+fn empty_chunked_body() {
+    let (received, received_rx) = mpsc::channel();
+    let addr = chunked_server(Duration::ZERO, received);
+    smol::block_on(async {
+        let request = http::Request::post(format!("http://{addr}/")).body(()).unwrap();
+        let mut write = RequestWrite::start(&request);
+        write.close().await.unwrap();
+        let (response, _) = write.response().await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+    });
+    assert_eq!(received_rx.recv_timeout(Duration::from_secs(5)).unwrap(), b"0\r\n\r\n");
+}
+
+#[test]
+fn empty_chunked_body_on_stream() {
+    smol::block_on(async {
+        let transport = MockTransport::new(&b"HTTP/1.1 204 No Content\r\n\r\n"[..]);
+        let written = transport.written();
+        let request = http::Request::post("http://example.com/").body(()).unwrap();
+        let (response, _) = RequestWrite::on_stream(&request, transport).response().await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+        let written = written.bytes();
+        assert!(String::from_utf8_lossy(&written).contains("transfer-encoding: chunked\r\n"));
+        assert!(written.ends_with(b"\r\n\r\n0\r\n\r\n"), "{:?}", String::from_utf8_lossy(&written));
+    })
+}
@@ -0,0 +1,166 @@
+mod common;
+
+use std::{io::Write, thread, time::Duration};
+
+use async_web_client::{remove_connection_headers, Connection, HttpError, MockTransport, RequestSend, TimeoutPhase, Timeouts};
+use futures::AsyncReadExt;
+
+fn get(addr: std::net::SocketAddr, path: &str) -> http::Request<Vec<u8>> {
+    http::Request::get(format!("http://{addr}{path}")).body(Vec::new()).unwrap()
+}
+
+#[test]
+fn body_until_close() {
+    let addr = common::serve_once(|mut stream| {
+        stream.write_all(b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\nfirst part, ").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        stream.write_all(b"second part").unwrap();
+    });
+    smol::block_on(async {
+        let request = get(addr, "/");
+        let mut connection = Connection::connect(request.uri()).await.unwrap();
+        let mut body = connection.send(&request).unwrap().await.unwrap().into_body();
+        let mut text = String::new();
+        body.read_to_string(&mut text).await.unwrap();
+        assert_eq!(text, "first part, second part");
+        connection.release(body).unwrap();
+        assert!(connection.is_closed());
+    })
+}
+
+#[test]
+fn http_10_body_until_close() {
+    let addr = common::respond_once(b"HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\n\r\nlegacy body");
+    smol::block_on(async {
+        let response = RequestSend::new(&get(addr, "/")).await.unwrap();
+        assert_eq!(response.version(), http::Version::HTTP_10);
+        let mut text = String::new();
+        response.into_body().read_to_string(&mut text).await.unwrap();
+        assert_eq!(text, "legacy body");
+    })
+}
+
+#[test]
+fn set_cookie_lines_stay_separate() {
+    let addr = common::respond_once(
+        b"HTTP/1.1 200 OK\r\n\
+        Set-Cookie: session=abc; Path=/\r\n\
+        Set-Cookie: theme=dark; Expires=Wed, 21 Oct 2026 07:28:00 GMT\r\n\
+        Set-Cookie: lang=en\r\n\
+        Content-Length: 0\r\n\r\n",
+    );
+    smol::block_on(async {
+        let response = RequestSend::new(&get(addr, "/")).await.unwrap();
+        let cookies: Vec<_> = response
+            .headers()
+            .get_all(http::header::SET_COOKIE)
+            .iter()
+            .map(|v| v.to_str().unwrap())
+            .collect();
+        assert_eq!(
+            cookies,
+            ["session=abc; Path=/", "theme=dark; Expires=Wed, 21 Oct 2026 07:28:00 GMT", "lang=en"]
+        );
+    })
+}
+
+// Each response is written with a single write, so head and body arrive in the same packet.
+#[test]
+fn head_and_body_in_one_packet() {
+    let cases: [(&'static [u8], &str); 3] = [
+        (b"HTTP/1.1 200 OK\r\nContent-Length: 13\r\n\r\n\r\n\r\nbody text", "\r\n\r\nbody text"),
+        (
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n6\r\n\r\nbody\r\n5\r\n text\r\n0\r\n\r\n",
+            "\r\nbody text",
+        ),
+        (b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n\r\nbody text", "\r\nbody text"),
+    ];
+    for (response, expected) in cases {
+        let addr = common::respond_once(response);
+        smol::block_on(async {
+            let mut text = String::new();
+            let response = RequestSend::new(&get(addr, "/")).await.unwrap();
+            response.into_body().read_to_string(&mut text).await.unwrap();
+            assert_eq!(text, expected);
+        })
+    }
+}
+
+#[test]
+fn head_and_body_in_one_read() {
+    smol::block_on(async {
+        let transport = MockTransport::new(&b"HTTP/1.1 200 OK\r\nContent-Length: 9\r\n\r\nbody text"[..]);
+        let request = http::Request::get("http://example.com/").body(Vec::new()).unwrap();
+        let mut connection = Connection::from_transport(transport.into());
+        let mut text = String::new();
+        let response = connection.send(&request).unwrap().await.unwrap();
+        response.into_body().read_to_string(&mut text).await.unwrap();
+        assert_eq!(text, "body text");
+    })
+}
+
+#[test]
+fn unfinished_head_times_out() {
+    let (done, done_rx) = std::sync::mpsc::channel::<()>();
+    let addr = common::serve_once(move |mut stream| {
+        // Header lines keep trickling in, but the head never ends.
+        let _ = stream.write_all(b"HTTP/1.1 200 OK\r\n");
+        for i in 0.. {
+            if done_rx.try_recv().is_ok() || stream.write_all(format!("X-Line-{i}: value\r\n").as_bytes()).is_err() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    });
+    smol::block_on(async {
+        let timeouts = Timeouts {
+            first_byte: Some(Duration::from_secs(5)),
+            head: Some(Duration::from_millis(200)),
+            ..Timeouts::default()
+        };
+        let result = RequestSend::new(&get(addr, "/")).with_timeouts(timeouts).await;
+        assert!(matches!(result, Err(HttpError::Timeout(TimeoutPhase::Head))), "{:?}", result.map(|_| ()));
+    });
+    done.send(()).unwrap();
+}
+
+fn closed_after(response: &'static [u8]) -> bool {
+    smol::block_on(async {
+        let mut connection = Connection::from_transport(MockTransport::new(response).into());
+        let request = http::Request::get("http://example.com/").body(Vec::new()).unwrap();
+        let mut body = connection.send(&request).unwrap().await.unwrap().into_body();
+        body.read_to_end(&mut Vec::new()).await.unwrap();
+        connection.release(body).unwrap();
+        connection.is_closed()
+    })
+}
+
+#[test]
+fn multi_token_connection_header() {
+    assert!(closed_after(
+        b"HTTP/1.1 200 OK\r\nConnection: keep-alive, Close\r\nContent-Length: 0\r\n\r\n"
+    ));
+    assert!(closed_after(
+        b"HTTP/1.1 200 OK\r\nConnection: x-trace\r\nConnection: close\r\nContent-Length: 0\r\n\r\n"
+    ));
+    assert!(!closed_after(
+        b"HTTP/1.1 200 OK\r\nConnection: x-trace, keep-alive\r\nContent-Length: 0\r\n\r\n"
+    ));
+    assert!(!closed_after(
+        b"HTTP/1.0 200 OK\r\nConnection: x-trace , Keep-Alive\r\nContent-Length: 0\r\n\r\n"
+    ));
+    assert!(closed_after(b"HTTP/1.0 200 OK\r\nConnection: x-trace\r\nContent-Length: 0\r\n\r\n"));
+}
+
+#[test]
+fn remove_connection_headers_named_in_tokens() {
+    let mut headers = http::HeaderMap::new();
+    headers.append("connection", "keep-alive, X-Trace".parse().unwrap());
+    headers.append("connection", "upgrade".parse().unwrap());
+    headers.insert("x-trace", "1".parse().unwrap());
+    headers.insert("upgrade", "websocket".parse().unwrap());
+    headers.insert("keep-alive", "timeout=5".parse().unwrap());
+    headers.insert("content-type", "text/plain".parse().unwrap());
+    remove_connection_headers(&mut headers);
+    assert_eq!(headers.keys().collect::<Vec<_>>(), ["content-type"]);
+}
@@ -0,0 +1,57 @@
+#![cfg(feature = "tls")]
+
+use std::sync::Arc;
+
+use async_web_client::{default_config_tls13_only, default_config_webpki, HttpError, RequestSend};
+use futures::AsyncReadExt;
+use futures_rustls::TlsAcceptor;
+use rustls::{AlertDescription, CertificateError, ServerConfig};
+
+// A server that only speaks TLS 1.2, with a self-signed certificate for `localhost`. It accepts a single handshake.
+fn tls12_server() -> u16 {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let der = cert.serialize_der().unwrap().into();
+    let key = rustls_pki_types::PrivateKeyDer::Pkcs8(cert.serialize_private_key_der().into());
+    let config = ServerConfig::builder_with_protocol_versions(&[&rustls::version::TLS12])
+        .with_no_client_auth()
+        .with_single_cert(vec![der], key)
+        .unwrap();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let listener = smol::net::TcpListener::try_from(listener).unwrap();
+    smol::spawn(async move {
+        let (tcp, _) = listener.accept().await.unwrap();
+        if let Ok(mut tls) = TlsAcceptor::from(Arc::new(config)).accept(tcp).await {
+            let _ = tls.read(&mut [0; 1]).await;
+        }
+    })
+    .detach();
+    port
+}
+
+fn handshake_error(client_config: Arc<rustls::ClientConfig>) -> rustls::Error {
+    let request = http::Request::get(format!("https://localhost:{}/", tls12_server()))
+        .body(Vec::new())
+        .unwrap();
+    match smol::block_on(RequestSend::new_with_client_config(&request, client_config)) {
+        Err(HttpError::TlsHandshake { error, .. }) => error,
+        result => panic!("expected a handshake error, got {:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn tls13_only_rejects_tls12_server() {
+    assert_eq!(
+        handshake_error(default_config_tls13_only()),
+        rustls::Error::AlertReceived(AlertDescription::ProtocolVersion)
+    );
+}
+
+// The same server gets past version negotiation with the default config, and only fails on its self-signed certificate.
+#[test]
+fn default_config_negotiates_tls12() {
+    assert_eq!(
+        handshake_error(default_config_webpki()),
+        rustls::Error::InvalidCertificate(CertificateError::UnknownIssuer)
+    );
+}
@@ -0,0 +1,295 @@
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    sync::{mpsc, Arc},
+    time::Duration,
+};
+
+use async_web_client::{WsCloseError, WsCloseFrame, WsConnectError, WsConnection, WsConnectionError, WsMessage, WsMessageError};
+
+mod common;
+
+// The key and accept values from the example in RFC 6455 section 1.3.
+const SAMPLE_NONCE: [u8; 16] = *b"the sample nonce";
+const SAMPLE_KEY: &str = "dGhlIHNhbXBsZSBub25jZQ==";
+const SAMPLE_ACCEPT: &str = "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=";
+
+/// Answers the upgrade request with `101 Switching Protocols` and the given `Sec-WebSocket-Accept`.
+fn upgrade_once(accept: Option<&'static str>) -> SocketAddr {
+    common::serve_once(move |mut stream| {
+        let mut response = b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n".to_vec();
+        if let Some(accept) = accept {
+            response.extend_from_slice(format!("Sec-WebSocket-Accept: {accept}\r\n").as_bytes());
+        }
+        response.extend_from_slice(b"\r\n");
+        stream.write_all(&response).unwrap();
+    })
+}
+
+/// Completes the handshake for [`SAMPLE_NONCE`] and hands the stream to `serve`.
+fn serve_ws(serve: impl FnOnce(TcpStream) + Send + 'static) -> WsConnection {
+    let addr = common::serve_once(move |mut stream| {
+        let response =
+            format!("HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: websocket\r\nSec-WebSocket-Accept: {SAMPLE_ACCEPT}\r\n\r\n");
+        stream.write_all(response.as_bytes()).unwrap();
+        serve(stream);
+    });
+    connect(addr).unwrap()
+}
+
+/// An unmasked frame, as sent by a server. Payloads have to be shorter than 126 bytes.
+fn frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![(fin as u8) << 7 | opcode, payload.len() as u8];
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Reads a masked frame from the client and returns its fin bit, opcode and unmasked payload.
+fn read_frame(stream: &mut TcpStream) -> Option<(bool, u8, Vec<u8>)> {
+    let mut head = [0; 2];
+    stream.read_exact(&mut head).ok()?;
+    assert_ne!(head[1] & 0x80, 0, "client frames are masked");
+    let len = match head[1] & 0x7f {
+        126 => {
+            let mut len = [0; 2];
+            stream.read_exact(&mut len).ok()?;
+            u16::from_be_bytes(len) as usize
+        }
+        127 => {
+            let mut len = [0; 8];
+            stream.read_exact(&mut len).ok()?;
+            u64::from_be_bytes(len) as usize
+        }
+        len => len as usize,
+    };
+    let mut mask = [0; 4];
+    stream.read_exact(&mut mask).ok()?;
+    let mut payload = vec![0; len];
+    stream.read_exact(&mut payload).ok()?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Some((head[0] & 0x80 != 0, head[0] & 0x0f, payload))
+}
+
+/// Reads frames until the client's close frame arrives and returns its code and reason.
+fn read_close(stream: &mut TcpStream) -> Option<(u16, String)> {
+    loop {
+        let (_, opcode, payload) = read_frame(stream)?;
+        if opcode == 0x8 {
+            let code = u16::from_be_bytes([payload[0], payload[1]]);
+            return Some((code, String::from_utf8(payload[2..].to_vec()).unwrap()));
+        }
+    }
+}
+
+fn close_frame(code: u16, reason: &str) -> Vec<u8> {
+    let mut payload = code.to_be_bytes().to_vec();
+    payload.extend_from_slice(reason.as_bytes());
+    frame(true, 0x8, &payload)
+}
+
+fn connect(addr: SocketAddr) -> Result<WsConnection, WsConnectError> {
+    let request = WsConnection::connect_request_builder_with_key(SAMPLE_NONCE)
+        .uri(format!("http://{addr}/"))
+        .body(Vec::new())
+        .unwrap();
+    smol::block_on(WsConnection::connect(&request))
+}
+
+#[test]
+fn sample_nonce_gives_sample_key() {
+    let request = WsConnection::connect_request_builder_with_key(SAMPLE_NONCE).body(()).unwrap();
+    assert_eq!(request.headers()["Sec-WebSocket-Key"], SAMPLE_KEY);
+}
+
+#[test]
+fn matching_accept_is_accepted() {
+    connect(upgrade_once(Some(SAMPLE_ACCEPT))).unwrap();
+}
+
+#[test]
+fn mismatching_accept_fails_handshake() {
+    match connect(upgrade_once(Some(SAMPLE_KEY))) {
+        Err(WsConnectError::HandshakeFailed { expected, received }) => {
+            assert_eq!(expected, SAMPLE_ACCEPT);
+            assert_eq!(received.unwrap(), SAMPLE_KEY);
+        }
+        result => panic!("expected HandshakeFailed, got {:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn missing_accept_fails_handshake() {
+    match connect(upgrade_once(None)) {
+        Err(WsConnectError::HandshakeFailed { expected, received }) => {
+            assert_eq!(expected, SAMPLE_ACCEPT);
+            assert_eq!(received, None);
+        }
+        result => panic!("expected HandshakeFailed, got {:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn fragments_are_reassembled_around_a_ping() {
+    let (pong, pongs) = mpsc::channel();
+    let mut ws = serve_ws(move |mut stream| {
+        stream.write_all(&frame(false, 0x1, b"Hel")).unwrap();
+        stream.write_all(&frame(true, 0x9, b"ping")).unwrap();
+        stream.write_all(&frame(false, 0x0, b"lo, ")).unwrap();
+        stream.write_all(&frame(true, 0x0, b"world")).unwrap();
+        pong.send(read_frame(&mut stream)).unwrap();
+    });
+    let message = smol::block_on(ws.next_message()).unwrap().unwrap();
+    assert_eq!(message, WsMessage::Text("Hello, world".to_string()));
+    let pong = pongs.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(pong, Some((true, 0xa, b"ping".to_vec())));
+}
+
+#[test]
+fn messages_over_the_limit_are_skipped() {
+    let mut ws = serve_ws(|mut stream| {
+        stream.write_all(&frame(false, 0x2, b"12345")).unwrap();
+        stream.write_all(&frame(true, 0x0, b"6789")).unwrap();
+        stream.write_all(&frame(true, 0x2, b"12345678")).unwrap();
+        stream.write_all(&frame(true, 0x1, b"ok")).unwrap();
+        read_frame(&mut stream);
+    })
+    .with_max_message_size(8);
+    smol::block_on(async {
+        assert!(matches!(ws.next_message().await, Some(Err(WsMessageError::TooLarge { limit: 8 }))));
+        assert_eq!(ws.next_message().await.unwrap().unwrap(), WsMessage::Binary(b"12345678".to_vec()));
+        assert_eq!(ws.next_message().await.unwrap().unwrap(), WsMessage::Text("ok".to_string()));
+    });
+}
+
+#[test]
+fn silent_peer_is_pinged_then_times_out() {
+    let (ping, pings) = mpsc::channel();
+    let mut ws = serve_ws(move |mut stream| {
+        ping.send(read_frame(&mut stream)).unwrap();
+        read_frame(&mut stream);
+    })
+    .with_ping_interval(Some(Duration::from_millis(50)));
+    match smol::block_on(ws.next_message()) {
+        Some(Err(WsMessageError::Connection(err))) => assert!(matches!(*err, WsConnectionError::Timeout), "{err}"),
+        message => panic!("expected a timeout, got {message:?}"),
+    }
+    assert_eq!(pings.recv_timeout(Duration::from_secs(5)).unwrap(), Some((true, 0x9, Vec::new())));
+}
+
+#[test]
+fn disabled_pings_wait_for_the_peer() {
+    let mut ws = serve_ws(|mut stream| {
+        stream.set_read_timeout(Some(Duration::from_millis(300))).unwrap();
+        assert_eq!(read_frame(&mut stream), None);
+        stream.write_all(&frame(true, 0x1, b"late")).unwrap();
+        read_frame(&mut stream);
+    })
+    .with_ping_interval(None);
+    assert_eq!(smol::block_on(ws.next_message()).unwrap().unwrap(), WsMessage::Text("late".to_string()));
+}
+
+#[test]
+fn close_is_answered_and_ends_the_connection() {
+    let (closed, closes) = mpsc::channel();
+    let ws = serve_ws(move |mut stream| {
+        let close = read_close(&mut stream).unwrap();
+        stream.write_all(&close_frame(close.0, &close.1)).unwrap();
+        // The client closes the transport once the handshake is complete.
+        closed.send((close, stream.read(&mut [0; 16]).unwrap())).unwrap();
+    });
+    smol::block_on(ws.close(1000, "bye")).unwrap();
+    let close = WsCloseFrame {
+        code: 1000,
+        reason: "bye".to_string(),
+    };
+    assert_eq!(ws.close_frame(), Some(close));
+    assert_eq!(closes.recv_timeout(Duration::from_secs(5)).unwrap(), ((1000, "bye".to_string()), 0));
+}
+
+#[test]
+fn close_from_the_peer_is_echoed() {
+    let (closed, closes) = mpsc::channel();
+    let mut ws = serve_ws(move |mut stream| {
+        stream.write_all(&close_frame(1001, "going away")).unwrap();
+        closed.send(read_close(&mut stream)).unwrap();
+    });
+    assert!(smol::block_on(ws.next_message()).is_none());
+    let close = WsCloseFrame {
+        code: 1001,
+        reason: "going away".to_string(),
+    };
+    assert_eq!(ws.close_frame(), Some(close));
+    assert_eq!(closes.recv_timeout(Duration::from_secs(5)).unwrap(), Some((1001, String::new())));
+}
+
+#[test]
+fn invalid_close_codes_are_rejected() {
+    let ws = serve_ws(|mut stream| drop(read_frame(&mut stream)));
+    smol::block_on(async {
+        for code in [0, 999, 1004, 1005, 1006, 1015, 1016, 2999, 5000] {
+            assert!(
+                matches!(ws.close(code, "").await, Err(WsCloseError::InvalidCode(c)) if c == code),
+                "{code}"
+            );
+        }
+        assert!(matches!(ws.close(1000, &"x".repeat(124)).await, Err(WsCloseError::ReasonTooLong(124))));
+    });
+    assert_eq!(ws.close_frame(), None);
+}
+
+#[test]
+fn protocol_errors_close_with_1002() {
+    let (closed, closes) = mpsc::channel();
+    let mut ws = serve_ws(move |mut stream| {
+        // A continuation frame without a message to continue.
+        stream.write_all(&frame(true, 0x0, b"stray")).unwrap();
+        closed.send(read_close(&mut stream)).unwrap();
+    });
+    match smol::block_on(ws.next_message()) {
+        None | Some(Err(WsMessageError::Connection(_))) => {}
+        message => panic!("expected the connection to fail, got {message:?}"),
+    }
+    assert!(matches!(ws.err().as_deref(), Some(WsConnectionError::Protocol(_))), "{:?}", ws.err());
+    let (code, _) = closes.recv_timeout(Duration::from_secs(5)).unwrap().unwrap();
+    assert_eq!(code, 1002);
+}
+
+#[test]
+fn characters_split_across_fragments_are_reassembled() {
+    let euro = "€".as_bytes();
+    let mut ws = serve_ws(move |mut stream| {
+        stream.write_all(&frame(false, 0x1, &[b'a', euro[0]])).unwrap();
+        stream.write_all(&frame(false, 0x0, &euro[1..2])).unwrap();
+        stream.write_all(&frame(true, 0x0, &[euro[2], b'b'])).unwrap();
+        read_frame(&mut stream);
+    });
+    assert_eq!(smol::block_on(ws.next_message()).unwrap().unwrap(), WsMessage::Text("a€b".to_string()));
+}
+
+/// Sends a text message in two fragments and returns the error the client failed with, after checking that it closed
+/// the connection with 1007.
+fn fail_with_1007(first: &'static [u8], last: &'static [u8]) -> Arc<WsConnectionError> {
+    let (closed, closes) = mpsc::channel();
+    let mut ws = serve_ws(move |mut stream| {
+        stream.write_all(&frame(false, 0x1, first)).unwrap();
+        stream.write_all(&frame(true, 0x0, last)).unwrap();
+        closed.send(read_close(&mut stream)).unwrap();
+    });
+    assert!(!matches!(smol::block_on(ws.next_message()), Some(Ok(_))));
+    let (code, _) = closes.recv_timeout(Duration::from_secs(5)).unwrap().unwrap();
+    assert_eq!(code, 1007);
+    ws.err().unwrap()
+}
+
+#[test]
+fn invalid_utf8_across_fragments_closes_with_1007() {
+    let euro = "€".as_bytes();
+    // The character is cut short by an ASCII byte in the next fragment.
+    let err = fail_with_1007(&euro[..2], b"x");
+    assert!(matches!(*err, WsConnectionError::InvalidUtf8), "{err}");
+    // The message ends in the middle of the character.
+    let err = fail_with_1007(b"a", &euro[..2]);
+    assert!(matches!(*err, WsConnectionError::IncompleteUtf8), "{err}");
+}